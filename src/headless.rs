@@ -0,0 +1,102 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use glam::Vec3;
+use image::RgbaImage;
+use wgpu::{Device, Queue, TextureFormat};
+
+use crate::{
+    renderer::{offscreen, WorldRenderer},
+    resources::{ResourcePacks, RESOURCE_PACK_DIR},
+    window::DAY_LENGTH_S,
+    world::{time::WorldTime, World},
+};
+
+#[cfg(test)]
+mod golden_tests;
+
+/// Everything that determines a frame rendered without a window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scene {
+    pub seed: u32,
+    pub camera_position: Vec3,
+    /// Camera yaw and pitch in degrees, yaw 0 faces X+ and positive pitch looks up
+    pub camera_rotation: (f32, f32),
+    /// Image width and height in pixels
+    pub size: (u32, u32),
+    /// Fraction of the day, 0.5 is noon
+    pub time_of_day: f32,
+    /// Render distance in chunks, the renderer's default if `None`
+    pub render_distance: Option<i32>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Scene {
+            seed: 0,
+            camera_position: Vec3::new(0.0, 100.0, 0.0),
+            camera_rotation: (45.0, -20.0),
+            size: (1280, 720),
+            time_of_day: 0.5,
+            render_distance: None,
+        }
+    }
+}
+
+/// Generate the world of `scene`, wait until all chunks in the render distance are loaded and
+/// render a single frame offscreen with the installed resource packs. Only the fallback adapter is
+/// used if `force_fallback_adapter` is set.
+pub fn render(scene: &Scene, force_fallback_adapter: bool) -> Result<RgbaImage> {
+    let (device, queue) = pollster::block_on(offscreen::request_device(force_fallback_adapter))?;
+    let resource_packs = ResourcePacks::load(Path::new(RESOURCE_PACK_DIR))?;
+    render_with_device(device, queue, &resource_packs, scene)
+}
+
+/// Like [`render`], on an existing device and with the given resource packs.
+pub fn render_with_device(
+    device: Device,
+    queue: Queue,
+    resource_packs: &ResourcePacks,
+    scene: &Scene,
+) -> Result<RgbaImage> {
+    let (width, height) = scene.size;
+
+    let mut world_renderer = WorldRenderer::new(
+        Arc::new(device),
+        Arc::new(queue),
+        TextureFormat::Rgba8UnormSrgb,
+        scene.size,
+        resource_packs,
+        scene.seed,
+    )?;
+
+    if let Some(render_distance) = scene.render_distance {
+        world_renderer.set_render_distance(render_distance);
+    }
+    let (yaw, pitch) = scene.camera_rotation;
+    let camera_controller = &mut world_renderer.camera_controller;
+    camera_controller.set_position(scene.camera_position);
+    // The camera uses half turns
+    camera_controller.set_rotation(yaw / 180.0, pitch / 180.0);
+    world_renderer
+        .environment
+        .set_world_time(&WorldTime::new(scene.time_of_day, DAY_LENGTH_S));
+
+    let world = Arc::new(Mutex::new(World::new(scene.seed)));
+    world_renderer.wait_for_chunks(world);
+
+    world_renderer.screenshot(width, height)
+}
+
+/// Render `scene` and save it as PNG to `path`.
+pub fn save_screenshot(scene: &Scene, force_fallback_adapter: bool, path: &Path) -> Result<()> {
+    let image = render(scene, force_fallback_adapter)?;
+    image
+        .save(path)
+        .with_context(|| format!("Failed to save {}", path.display()))?;
+    log::info!("Saved screenshot to {}", path.display());
+    Ok(())
+}
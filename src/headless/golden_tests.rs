@@ -0,0 +1,176 @@
+//! Renders fixed scenes on the fallback adapter and compares them against reference images in
+//! `tests/golden`, so changes to the shaders or the mesher show up as test failures.
+//!
+//! Set `UPDATE_GOLDEN_IMAGES=1` to write the references from the current renderings, for new
+//! scenes and after intended changes. Missing references fail the tests otherwise. On mismatches,
+//! the rendering and an image highlighting the differences are written to `target/golden`.
+//!
+//! The tests fail without a fallback adapter such as llvmpipe, unless `SKIP_GOLDEN_IMAGES=1` is
+//! set. Scenes are rendered with the embedded default resource pack only, so installed packs and
+//! edited pack sources don't change them.
+
+use std::{env, fs, path::PathBuf};
+
+use glam::Vec3;
+use image::{Rgba, RgbaImage};
+
+use super::*;
+use crate::resources::ResourcePacks;
+
+/// Pixels differing by more than this fraction of the largest possible perceptual difference are
+/// counted as mismatched
+const PIXEL_THRESHOLD: f32 = 0.1;
+/// Images match if at most this fraction of their pixels is mismatched, which absorbs small
+/// rasterization differences between driver versions
+const MAX_MISMATCHED_FRACTION: f32 = 0.005;
+
+fn reference_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden")
+}
+
+/// Perceptual difference of two colors from 0 to 1, weighing brightness differences more than
+/// differences in hue, as in the YIQ color space.
+fn color_difference(a: &Rgba<u8>, b: &Rgba<u8>) -> f32 {
+    let yiq = |Rgba([r, g, b, _]): &Rgba<u8>| {
+        let [r, g, b] = [*r, *g, *b].map(|channel| channel as f32 / 255.0);
+        Vec3::new(
+            0.2989 * r + 0.5866 * g + 0.1145 * b,
+            0.5960 * r - 0.2742 * g - 0.3218 * b,
+            0.2115 * r - 0.5226 * g + 0.3111 * b,
+        )
+    };
+    let delta = yiq(a) - yiq(b);
+    let squared =
+        0.5053 * delta.x * delta.x + 0.299 * delta.y * delta.y + 0.1957 * delta.z * delta.z;
+    // Largest value of `squared`, between black and white
+    const MAX_SQUARED: f32 = 0.5053;
+    (squared / MAX_SQUARED).sqrt()
+}
+
+/// Number of mismatched pixels and an image showing them in red on top of a faded `expected`.
+fn compare(expected: &RgbaImage, actual: &RgbaImage) -> (usize, RgbaImage) {
+    let mut mismatched = 0;
+    let diff = RgbaImage::from_fn(expected.width(), expected.height(), |x, y| {
+        let expected_pixel = expected.get_pixel(x, y);
+        if color_difference(expected_pixel, actual.get_pixel(x, y)) > PIXEL_THRESHOLD {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let Rgba([r, g, b, _]) = *expected_pixel;
+            let gray = (r as u32 + g as u32 + b as u32) / 3;
+            let faded = (gray / 4 + 191) as u8;
+            Rgba([faded, faded, faded, 255])
+        }
+    });
+    (mismatched, diff)
+}
+
+/// Render `scene` and compare it against the reference image `name`.
+fn check_scene(name: &str, scene: &Scene) {
+    let (device, queue) = match pollster::block_on(offscreen::request_device(true)) {
+        Ok(device) => device,
+        Err(_) if env::var_os("SKIP_GOLDEN_IMAGES").is_some() => {
+            eprintln!("Skipping golden image test {name}: no fallback adapter available");
+            return;
+        }
+        Err(error) => panic!(
+            "Golden image test {name} needs a fallback adapter such as llvmpipe, set \
+             SKIP_GOLDEN_IMAGES=1 to skip it: {error:#}"
+        ),
+    };
+    let actual = render_with_device(device, queue, &ResourcePacks::embedded(), scene).unwrap();
+
+    let reference_path = reference_dir().join(format!("{name}.png"));
+    if env::var_os("UPDATE_GOLDEN_IMAGES").is_some() {
+        fs::create_dir_all(reference_dir()).unwrap();
+        actual.save(&reference_path).unwrap();
+        eprintln!("Wrote reference image {}", reference_path.display());
+        return;
+    }
+    assert!(
+        reference_path.exists(),
+        "Missing reference image {}, set UPDATE_GOLDEN_IMAGES=1 to create it",
+        reference_path.display()
+    );
+    let expected = image::open(&reference_path).unwrap().to_rgba8();
+    assert_eq!(
+        expected.dimensions(),
+        actual.dimensions(),
+        "{name} has a different size than its reference"
+    );
+
+    let (mismatched, diff) = compare(&expected, &actual);
+    let mismatched_fraction = mismatched as f32 / (actual.width() * actual.height()) as f32;
+    if mismatched_fraction > MAX_MISMATCHED_FRACTION {
+        fs::create_dir_all(output_dir()).unwrap();
+        let actual_path = output_dir().join(format!("{name}.png"));
+        let diff_path = output_dir().join(format!("{name}.diff.png"));
+        actual.save(&actual_path).unwrap();
+        diff.save(&diff_path).unwrap();
+        panic!(
+            "{name} differs from its reference in {:.2}% of its pixels, see {} and {}",
+            100.0 * mismatched_fraction,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn terrain_scene() -> Scene {
+    Scene {
+        seed: 0,
+        camera_position: Vec3::new(0.0, 75.0, 0.0),
+        camera_rotation: (30.0, -30.0),
+        size: (192, 108),
+        time_of_day: 0.5,
+        render_distance: Some(3),
+    }
+}
+
+#[test]
+fn perceptual_difference() {
+    let black = Rgba([0, 0, 0, 255]);
+    let white = Rgba([255, 255, 255, 255]);
+    assert_eq!(color_difference(&black, &black), 0.0);
+    assert!((color_difference(&black, &white) - 1.0).abs() < 1e-3);
+    assert!(color_difference(&Rgba([100, 150, 200, 255]), &Rgba([102, 150, 198, 255])) < 0.02);
+
+    let image = RgbaImage::from_pixel(4, 4, white);
+    let mut changed = image.clone();
+    changed.put_pixel(1, 2, black);
+    let (mismatched, diff) = compare(&image, &changed);
+    assert_eq!(mismatched, 1);
+    assert_eq!(*diff.get_pixel(1, 2), Rgba([255, 0, 0, 255]));
+}
+
+#[test]
+fn golden_terrain_noon() {
+    check_scene("terrain_noon", &terrain_scene());
+}
+
+#[test]
+fn golden_terrain_sunset() {
+    check_scene(
+        "terrain_sunset",
+        &Scene {
+            time_of_day: 0.74,
+            ..terrain_scene()
+        },
+    );
+}
+
+#[test]
+fn golden_close_up() {
+    check_scene(
+        "close_up",
+        &Scene {
+            camera_position: Vec3::new(4.5, 12.0, 4.5),
+            camera_rotation: (0.0, -60.0),
+            ..terrain_scene()
+        },
+    );
+}
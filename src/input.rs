@@ -0,0 +1,361 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt, fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Context, Result};
+use serde::{de::IntoDeserializer, Deserialize, Serialize};
+use winit::{event::MouseButton, keyboard::KeyCode};
+
+pub mod recording;
+
+/// File with the user's input bindings, overriding the default bindings
+pub const INPUT_CONFIG_FILE: &str = "input.toml";
+const DEFAULT_CONFIG: &str = include_str!("../res/input.toml");
+
+/// Physical key or mouse button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Input {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Key(key) => write!(f, "{key:?}"),
+            Input::Mouse(MouseButton::Left) => write!(f, "MouseLeft"),
+            Input::Mouse(MouseButton::Right) => write!(f, "MouseRight"),
+            Input::Mouse(MouseButton::Middle) => write!(f, "MouseMiddle"),
+            Input::Mouse(MouseButton::Back) => write!(f, "MouseBack"),
+            Input::Mouse(MouseButton::Forward) => write!(f, "MouseForward"),
+            Input::Mouse(MouseButton::Other(button)) => write!(f, "Mouse{button}"),
+        }
+    }
+}
+
+impl FromStr for Input {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        let button = match name {
+            "MouseLeft" => Some(MouseButton::Left),
+            "MouseRight" => Some(MouseButton::Right),
+            "MouseMiddle" => Some(MouseButton::Middle),
+            "MouseBack" => Some(MouseButton::Back),
+            "MouseForward" => Some(MouseButton::Forward),
+            _ => name
+                .strip_prefix("Mouse")
+                .and_then(|button| button.parse().ok())
+                .map(MouseButton::Other),
+        };
+        if let Some(button) = button {
+            return Ok(Input::Mouse(button));
+        }
+
+        KeyCode::deserialize(name.into_deserializer())
+            .map(Input::Key)
+            .map_err(|_: serde::de::value::Error| anyhow!("Unknown input {name}"))
+    }
+}
+
+impl TryFrom<String> for Input {
+    type Error = anyhow::Error;
+
+    fn try_from(name: String) -> Result<Self> {
+        name.parse()
+    }
+}
+
+impl From<Input> for String {
+    fn from(input: Input) -> Self {
+        input.to_string()
+    }
+}
+
+/// Something the player can do, bound to one or more inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    /// Jump while walking, ascend while flying
+    Jump,
+    /// Descend while flying
+    Descend,
+    Sprint,
+    BreakBlock,
+    PlaceBlock,
+    #[serde(rename = "select_block_1")]
+    SelectBlock1,
+    #[serde(rename = "select_block_2")]
+    SelectBlock2,
+    #[serde(rename = "select_block_3")]
+    SelectBlock3,
+    #[serde(rename = "select_block_4")]
+    SelectBlock4,
+    #[serde(rename = "select_block_5")]
+    SelectBlock5,
+    #[serde(rename = "select_block_6")]
+    SelectBlock6,
+    #[serde(rename = "select_block_7")]
+    SelectBlock7,
+    IncreaseRenderDistance,
+    DecreaseRenderDistance,
+    CycleFogMode,
+    SkipTime,
+    PauseTime,
+    CycleShadowQuality,
+    ToggleFlying,
+    /// Replace the next pressed input by the one pressed after it
+    Rebind,
+    Screenshot,
+    ToggleDebugOverlay,
+    ExportFrameTimes,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Same names as in the input config
+        match toml::Value::try_from(self) {
+            Ok(toml::Value::String(name)) => f.write_str(&name),
+            _ => Err(fmt::Error),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Action::deserialize(name.into_deserializer())
+            .map_err(|_: serde::de::value::Error| anyhow!("Unknown action {name}"))
+    }
+}
+
+impl Action {
+    /// Index of the block selected by this action, counting solid blocks from 0.
+    pub fn block_selection_index(&self) -> Option<usize> {
+        match self {
+            Action::SelectBlock1 => Some(0),
+            Action::SelectBlock2 => Some(1),
+            Action::SelectBlock3 => Some(2),
+            Action::SelectBlock4 => Some(3),
+            Action::SelectBlock5 => Some(4),
+            Action::SelectBlock6 => Some(5),
+            Action::SelectBlock7 => Some(6),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct InputConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mouse_sensitivity: Option<f32>,
+    #[serde(default)]
+    bindings: BTreeMap<Action, Vec<Input>>,
+}
+
+/// Maps inputs to actions.
+pub struct InputMap {
+    /// Camera rotation per mouse movement step, in half turns
+    pub mouse_sensitivity: f32,
+    bindings: BTreeMap<Action, Vec<Input>>,
+    /// File the bindings are saved to after rebinding
+    path: PathBuf,
+    /// Input to replace by the next pressed input, while rebinding
+    rebinding: Option<Option<Input>>,
+}
+
+impl InputMap {
+    /// Load the default bindings, with the ones from the config file at `path` replacing them if
+    /// it exists.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut input_map = InputMap::with_defaults(path);
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound => return Ok(input_map),
+            Err(error) => return Err(error.into()),
+        };
+        let config: InputConfig = toml::from_str(&contents)
+            .with_context(|| format!("Invalid input config {}", path.display()))?;
+
+        if let Some(mouse_sensitivity) = config.mouse_sensitivity {
+            input_map.mouse_sensitivity = mouse_sensitivity;
+        }
+        input_map.bindings.extend(config.bindings);
+        log::info!("Loaded input bindings from {}", path.display());
+
+        Ok(input_map)
+    }
+
+    /// Default bindings, which are saved to `path` after rebinding.
+    pub fn with_defaults(path: &Path) -> Self {
+        let config: InputConfig =
+            toml::from_str(DEFAULT_CONFIG).expect("Default input config is valid");
+
+        InputMap {
+            mouse_sensitivity: config.mouse_sensitivity.unwrap_or(0.0015),
+            bindings: config.bindings,
+            path: path.to_owned(),
+            rebinding: None,
+        }
+    }
+
+    /// Camera rotation by a raw mouse movement, as yaw and pitch in half turns.
+    pub fn mouse_rotation(&self, (dx, dy): (f64, f64)) -> (f32, f32) {
+        (
+            -(dx as f32) * self.mouse_sensitivity,
+            -(dy as f32) * self.mouse_sensitivity,
+        )
+    }
+
+    /// Actions bound to `input`.
+    pub fn actions(&self, input: Input) -> Vec<Action> {
+        self.bindings
+            .iter()
+            .filter(|(_, inputs)| inputs.contains(&input))
+            .map(|(action, _)| *action)
+            .collect()
+    }
+
+    /// Actions with at least one of their inputs pressed.
+    pub fn active_actions(&self, pressed_inputs: &HashSet<Input>) -> HashSet<Action> {
+        self.bindings
+            .iter()
+            .filter(|(_, inputs)| inputs.iter().any(|input| pressed_inputs.contains(input)))
+            .map(|(action, _)| *action)
+            .collect()
+    }
+
+    /// Start rebinding: the next pressed input is replaced by the one pressed after it.
+    pub fn start_rebinding(&mut self) {
+        self.rebinding = Some(None);
+        log::info!("{}", self.rebinding_prompt().unwrap_or_default());
+    }
+
+    /// Pass a pressed input to an ongoing rebinding. Returns `false` if no rebinding is in
+    /// progress, in which case the input should trigger its actions as usual.
+    pub fn handle_rebinding(&mut self, input: Input) -> bool {
+        match self.rebinding {
+            None => false,
+            Some(None) => {
+                self.rebinding = Some(Some(input));
+                log::info!("{}", self.rebinding_prompt().unwrap_or_default());
+                true
+            }
+            Some(Some(old_input)) => {
+                self.rebinding = None;
+                let actions = self.rebind(old_input, input);
+                log::info!("Rebound {old_input} to {input} for {actions:?}");
+                if let Err(error) = self.save() {
+                    log::error!("Failed to save input bindings: {error:#}");
+                }
+                true
+            }
+        }
+    }
+
+    /// Instructions for the ongoing rebinding, if any.
+    pub fn rebinding_prompt(&self) -> Option<String> {
+        match self.rebinding? {
+            None => Some("Rebinding: press the input to replace".to_owned()),
+            Some(input) => Some(format!("Rebinding {input}: press the new input")),
+        }
+    }
+
+    /// Replace `old_input` by `new_input` in all bindings. Returns the changed actions.
+    pub fn rebind(&mut self, old_input: Input, new_input: Input) -> Vec<Action> {
+        let mut changed_actions = Vec::new();
+        for (action, inputs) in &mut self.bindings {
+            if let Some(input) = inputs.iter_mut().find(|input| **input == old_input) {
+                *input = new_input;
+                changed_actions.push(*action);
+            }
+        }
+        changed_actions
+    }
+
+    /// Write all bindings to the config file.
+    pub fn save(&self) -> Result<()> {
+        let config = InputConfig {
+            mouse_sensitivity: Some(self.mouse_sensitivity),
+            bindings: self.bindings.clone(),
+        };
+        fs::write(&self.path, toml::to_string(&config)?)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_inputs() {
+        assert_eq!("KeyW".parse::<Input>().unwrap(), Input::Key(KeyCode::KeyW));
+        assert_eq!(
+            "MouseLeft".parse::<Input>().unwrap(),
+            Input::Mouse(MouseButton::Left)
+        );
+        assert_eq!(
+            "Mouse4".parse::<Input>().unwrap(),
+            Input::Mouse(MouseButton::Other(4))
+        );
+        assert!("NotAKey".parse::<Input>().is_err());
+
+        for input in [
+            Input::Key(KeyCode::Digit1),
+            Input::Mouse(MouseButton::Forward),
+        ] {
+            assert_eq!(input.to_string().parse::<Input>().unwrap(), input);
+        }
+
+        assert_eq!(Action::SelectBlock1.to_string(), "select_block_1");
+        for action in [Action::MoveForward, Action::SelectBlock7] {
+            assert_eq!(action.to_string().parse::<Action>().unwrap(), action);
+        }
+        assert!("not_an_action".parse::<Action>().is_err());
+    }
+
+    #[test]
+    fn default_bindings() {
+        let input_map = InputMap::with_defaults(Path::new(INPUT_CONFIG_FILE));
+        assert_eq!(
+            input_map.actions(Input::Key(KeyCode::KeyW)),
+            [Action::MoveForward]
+        );
+        assert_eq!(
+            input_map.actions(Input::Mouse(MouseButton::Left)),
+            [Action::BreakBlock]
+        );
+    }
+
+    #[test]
+    fn rebinding_survives_saving() {
+        let path = std::env::temp_dir().join(format!("input-test-{}.toml", std::process::id()));
+        let mut input_map = InputMap::with_defaults(&path);
+        input_map.start_rebinding();
+        assert!(input_map.rebinding_prompt().is_some());
+        assert!(input_map.handle_rebinding(Input::Key(KeyCode::KeyW)));
+        assert!(input_map.handle_rebinding(Input::Key(KeyCode::ArrowUp)));
+        assert!(input_map.rebinding_prompt().is_none());
+        assert!(!input_map.handle_rebinding(Input::Key(KeyCode::KeyW)));
+
+        let input_map = InputMap::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(input_map.actions(Input::Key(KeyCode::KeyW)).is_empty());
+        assert_eq!(
+            input_map.active_actions(&HashSet::from([Input::Key(KeyCode::ArrowUp)])),
+            HashSet::from([Action::MoveForward])
+        );
+    }
+}
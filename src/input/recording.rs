@@ -0,0 +1,202 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use crate::input::Action;
+
+const HEADER: &str = "# input recording, the world seed followed by one event per line: \
+<tick> <trigger|start|stop|look> <action|yaw pitch>";
+
+/// Input resolved to actions, recorded for replaying it later. Recording actions instead of the
+/// raw input keeps replays independent of the bindings and mouse sensitivity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    /// Action bound to a pressed input
+    Triggered(Action),
+    /// Held action became active
+    Started(Action),
+    /// Held action is no longer active
+    Stopped(Action),
+    /// Camera rotation as yaw and pitch in half turns
+    Look(f32, f32),
+}
+
+/// Input event and the simulation tick it happened before.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedInputEvent {
+    pub tick: u64,
+    pub event: InputEvent,
+}
+
+impl fmt::Display for TimedInputEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.event {
+            InputEvent::Triggered(action) => write!(f, "{} trigger {action}", self.tick),
+            InputEvent::Started(action) => write!(f, "{} start {action}", self.tick),
+            InputEvent::Stopped(action) => write!(f, "{} stop {action}", self.tick),
+            // Display of floats round trips exactly
+            InputEvent::Look(yaw, pitch) => write!(f, "{} look {yaw} {pitch}", self.tick),
+        }
+    }
+}
+
+impl FromStr for TimedInputEvent {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> Result<Self> {
+        let mut parts = line.split_whitespace();
+        let mut next = || parts.next().ok_or_else(|| anyhow!("Incomplete event"));
+
+        let tick = next()?.parse().context("Invalid tick")?;
+        let event = match next()? {
+            "trigger" => InputEvent::Triggered(next()?.parse()?),
+            "start" => InputEvent::Started(next()?.parse()?),
+            "stop" => InputEvent::Stopped(next()?.parse()?),
+            "look" => InputEvent::Look(
+                next()?.parse().context("Invalid rotation")?,
+                next()?.parse().context("Invalid rotation")?,
+            ),
+            kind => bail!("Unknown event {kind}"),
+        };
+
+        Ok(TimedInputEvent { tick, event })
+    }
+}
+
+/// Writes input events to a file as they happen.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    /// Start a recording of the world generated from `seed`.
+    pub fn create(path: &Path, seed: u32) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{HEADER}")?;
+        writeln!(writer, "seed {seed}")?;
+        log::info!("Recording input to {}", path.display());
+
+        Ok(InputRecorder { writer })
+    }
+
+    pub fn record(&mut self, event: TimedInputEvent) {
+        if let Err(error) = writeln!(self.writer, "{event}") {
+            log::error!("Failed to record input: {error}");
+        }
+    }
+}
+
+/// Recorded input events, replayed tick by tick.
+pub struct InputReplay {
+    events: VecDeque<TimedInputEvent>,
+}
+
+impl InputReplay {
+    /// Load a recording, which has to be of the world generated from `seed`.
+    pub fn load(path: &Path, seed: u32) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut lines = contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'));
+
+        let recorded_seed: u32 = lines
+            .next()
+            .and_then(|(_, line)| line.strip_prefix("seed "))
+            .ok_or_else(|| anyhow!("Missing world seed in {}", path.display()))?
+            .trim()
+            .parse()
+            .context("Invalid world seed")?;
+        ensure!(
+            recorded_seed == seed,
+            "{} was recorded with seed {recorded_seed}, but the world seed is {seed}",
+            path.display()
+        );
+
+        let events = lines
+            .map(|(index, line)| {
+                line.parse()
+                    .with_context(|| format!("Invalid event in line {}", index + 1))
+            })
+            .collect::<Result<VecDeque<TimedInputEvent>>>()?;
+        log::info!(
+            "Replaying {} input events from {}",
+            events.len(),
+            path.display()
+        );
+
+        Ok(InputReplay { events })
+    }
+
+    /// Remove and return the events to apply before simulating `tick`.
+    pub fn events_before(&mut self, tick: u64) -> Vec<InputEvent> {
+        let count = self
+            .events
+            .iter()
+            .take_while(|event| event.tick <= tick)
+            .count();
+        self.events
+            .drain(..count)
+            .map(|event| event.event)
+            .collect()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recording_round_trips() {
+        let path = std::env::temp_dir().join(format!("recording-test-{}.txt", std::process::id()));
+        let events = [
+            TimedInputEvent {
+                tick: 0,
+                event: InputEvent::Started(Action::MoveForward),
+            },
+            TimedInputEvent {
+                tick: 0,
+                event: InputEvent::Look(-0.004875, 0.00015),
+            },
+            TimedInputEvent {
+                tick: 42,
+                event: InputEvent::Triggered(Action::SelectBlock3),
+            },
+            TimedInputEvent {
+                tick: 42,
+                event: InputEvent::Stopped(Action::MoveForward),
+            },
+        ];
+
+        let mut recorder = InputRecorder::create(&path, 7).unwrap();
+        for event in events {
+            recorder.record(event);
+        }
+        drop(recorder);
+
+        assert!(InputReplay::load(&path, 8).is_err());
+        let mut replay = InputReplay::load(&path, 7).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(replay.events_before(0), [events[0].event, events[1].event]);
+        assert!(replay.events_before(41).is_empty());
+        assert_eq!(
+            replay.events_before(100),
+            [events[2].event, events[3].event]
+        );
+        assert!(replay.is_finished());
+    }
+}
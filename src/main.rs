@@ -1,9 +1,36 @@
+mod headless;
+mod input;
+mod options;
+mod renderer;
+mod resources;
 mod texture;
 mod window;
 mod world;
-mod renderer;
+
+use std::{env, process};
+
+use options::{Options, USAGE};
 
 fn main() {
     env_logger::init();
-    pollster::block_on(window::run());
+
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{error:#}\n\n{USAGE}");
+            process::exit(2);
+        }
+    };
+
+    if let Some(path) = &options.screenshot {
+        if let Err(error) =
+            headless::save_screenshot(&options.scene, options.fallback_adapter, path)
+        {
+            log::error!("{error:#}");
+            process::exit(1);
+        }
+        return;
+    }
+
+    pollster::block_on(window::run(options));
 }
@@ -0,0 +1,131 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, ensure, Context, Result};
+use glam::Vec3;
+
+use crate::headless::Scene;
+
+pub const USAGE: &str = "\
+Usage: mc [options]
+
+Options:
+    --seed <n>               World seed, 0 by default
+    --record <file>          Record all input to <file>
+    --replay <file>          Replay input recorded with --record instead of handling user input
+
+Headless rendering:
+    --screenshot <file>      Render a single frame without a window and save it as PNG
+    --position <x,y,z>       Camera position, 0,100,0 by default
+    --rotation <yaw,pitch>   Camera yaw and pitch in degrees, 45,-20 by default
+    --size <width>x<height>  Image size, 1280x720 by default
+    --time <fraction>        Time of day from 0 to 1, 0.5 (noon) by default
+    --render-distance <n>    Render distance in chunks
+    --fallback-adapter       Only use the fallback adapter, usually a software renderer";
+
+/// Command line options.
+#[derive(Debug, Default)]
+pub struct Options {
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    /// Render `scene` to this file instead of opening a window
+    pub screenshot: Option<PathBuf>,
+    /// The world seed is also used when playing
+    pub scene: Scene,
+    pub fallback_adapter: bool,
+}
+
+impl Options {
+    /// Parse the command line arguments, without the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().with_context(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--seed" => options.scene.seed = value()?.parse().context("Invalid seed")?,
+                "--record" => options.record = Some(value()?.into()),
+                "--replay" => options.replay = Some(value()?.into()),
+                "--screenshot" => options.screenshot = Some(value()?.into()),
+                "--position" => {
+                    let [x, y, z] = parse_list(&value()?, ',').context("Invalid position")?;
+                    options.scene.camera_position = Vec3::new(x, y, z);
+                }
+                "--rotation" => {
+                    let [yaw, pitch] = parse_list(&value()?, ',').context("Invalid rotation")?;
+                    options.scene.camera_rotation = (yaw, pitch);
+                }
+                "--size" => {
+                    let [width, height] = parse_list(&value()?, 'x').context("Invalid size")?;
+                    ensure!(width > 0 && height > 0, "Size must not be zero");
+                    options.scene.size = (width, height);
+                }
+                "--time" => options.scene.time_of_day = value()?.parse().context("Invalid time")?,
+                "--render-distance" => {
+                    options.scene.render_distance =
+                        Some(value()?.parse().context("Invalid render distance")?)
+                }
+                "--fallback-adapter" => options.fallback_adapter = true,
+                _ => bail!("Unknown argument {arg}"),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Parse exactly `N` values separated by `separator`.
+fn parse_list<T: std::str::FromStr, const N: usize>(value: &str, separator: char) -> Result<[T; N]>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let values = value
+        .split(separator)
+        .map(|value| value.trim().parse())
+        .collect::<Result<Vec<T>, _>>()?;
+    let count = values.len();
+    values
+        .try_into()
+        .ok()
+        .with_context(|| format!("Expected {N} values, got {count}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_scene() {
+        let options = parse(&[
+            "--seed",
+            "7",
+            "--screenshot",
+            "out.png",
+            "--position",
+            "1.5,80,-3",
+            "--size",
+            "64x32",
+        ])
+        .unwrap();
+        assert_eq!(options.screenshot, Some(PathBuf::from("out.png")));
+        assert_eq!(options.scene.seed, 7);
+        assert_eq!(options.scene.camera_position, Vec3::new(1.5, 80.0, -3.0));
+        assert_eq!(options.scene.size, (64, 32));
+        assert_eq!(
+            options.scene.camera_rotation,
+            Scene::default().camera_rotation
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert!(parse(&["--position", "1,2"]).is_err());
+        assert!(parse(&["--size", "0x10"]).is_err());
+        assert!(parse(&["--record"]).is_err());
+        assert!(parse(&["--unknown"]).is_err());
+    }
+}
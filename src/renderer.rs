@@ -1,30 +1,88 @@
 use std::{
-    mem,
+    collections::HashSet,
+    ffi::OsStr,
+    fs, mem,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
     thread::{self, JoinHandle},
+    time::Duration,
 };
 
+use anyhow::Result;
 use bytemuck::{Pod, Zeroable};
+use image::RgbaImage;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    BindGroup, BindGroupDescriptor, BindGroupEntry, BindingType, BlendState, Buffer, BufferAddress,
-    BufferBindingType, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites,
-    CompareFunction, DepthBiasState, DepthStencilState, Device, Face, FragmentState, FrontFace,
-    MultisampleState, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
-    Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor,
-    ShaderSource, ShaderStages, StencilState, SurfaceConfiguration, TextureFormat, VertexAttribute,
-    VertexBufferLayout, VertexFormat, VertexState, VertexStepMode,
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingType, BlendState,
+    Buffer, BufferAddress, BufferBindingType, BufferDescriptor, BufferUsages, Color,
+    ColorTargetState, ColorWrites, CommandEncoder, CommandEncoderDescriptor, CompareFunction,
+    DepthBiasState, DepthStencilState, Device, Face, FragmentState, FrontFace, LoadOp,
+    MultisampleState, Operations, PipelineLayout, PipelineLayoutDescriptor, PolygonMode,
+    PrimitiveState, PrimitiveTopology, Queue, RenderPass, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages,
+    StencilState, StoreOp, TextureFormat, TextureView, VertexAttribute, VertexBufferLayout,
+    VertexFormat, VertexState, VertexStepMode,
 };
 
 use crate::{
-    renderer::ui_renderer::Reticle,
+    renderer::{
+        cloud_renderer::Clouds,
+        environment::Environment,
+        gpu_profiler::{GpuProfiler, GpuTimings, ProfiledPass},
+        hot_reload::HotReload,
+        offscreen::OffscreenTarget,
+        selection_renderer::SelectionOutline,
+        shadow_renderer::{ShadowQuality, Shadows},
+        sky_renderer::Sky,
+        text_renderer::TextRenderer,
+        ui_renderer::Reticle,
+    },
+    resources::ResourcePacks,
     texture,
-    world::{camera::CameraController, World, CHUNK_DIMENSIONS, VERTICAL_CHUNK_COUNT},
+    world::{
+        blocks::Direction, camera::CameraController, visibility, RaycastHit, World,
+        CHUNK_DIMENSIONS, CHUNK_WIDTH_BITS, LOD_LEVELS, VERTICAL_CHUNK_COUNT, WORLD_HEIGHT,
+    },
 };
 
+mod cloud_renderer;
+pub mod environment;
+pub mod gpu_profiler;
+mod hot_reload;
+pub mod offscreen;
+mod preprocessor;
+mod selection_renderer;
+pub mod shadow_renderer;
+mod sky_renderer;
+pub mod text_renderer;
 mod ui_renderer;
 
-const CHUNK_RENDER_DISTANCE: i32 = 4;
+/// Render distance in chunks used at startup
+const DEFAULT_RENDER_DISTANCE: i32 = 12;
+const MIN_RENDER_DISTANCE: i32 = 2;
+const MAX_RENDER_DISTANCE: i32 = 32;
+/// Loaded chunk columns are only unloaded once they are this many chunks outside of the render
+/// distance, so moving back and forth along a chunk border doesn't regenerate chunks
+const UNLOAD_DISTANCE_MARGIN: i32 = 2;
+/// Chunks up to this distance from the camera chunk are rendered in full detail, every further
+/// ring of doubled width uses the next coarser level of detail.
+const FULL_DETAIL_DISTANCE: i32 = 4;
+/// Altitude of the cloud layer in blocks, above the highest terrain
+const CLOUD_HEIGHT: f32 = (WORLD_HEIGHT + 32) as f32;
+/// Blocks further away from the camera than this can't be targeted
+pub const REACH_DISTANCE: f32 = 8.0;
+
+/// Level of detail of a chunk `distance` chunks away from the camera chunk.
+fn lod_for_distance(distance: i32) -> usize {
+    let mut lod = 0;
+    let mut ring_end = FULL_DETAIL_DISTANCE;
+    while distance > ring_end && lod < LOD_LEVELS - 1 {
+        lod += 1;
+        ring_end *= 2;
+    }
+    lod
+}
 
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 #[repr(C)]
@@ -73,6 +131,21 @@ const CUBE_FACE_VERTICES: &[Vertex] = &[
     },
 ];
 
+// Layout of `CubeFaceInstance::attributes`, starting at the least significant bit: x, y and z
+// position inside of the chunk, texture index, face direction and level of detail
+const POSITION_MASK: u32 = (1 << CHUNK_WIDTH_BITS) - 1;
+const TEXTURE_INDEX_SHIFT: u32 = 3 * CHUNK_WIDTH_BITS;
+const TEXTURE_INDEX_BITS: u32 = 8;
+const TEXTURE_INDEX_MASK: u32 = (1 << TEXTURE_INDEX_BITS) - 1;
+const DIRECTION_SHIFT: u32 = TEXTURE_INDEX_SHIFT + TEXTURE_INDEX_BITS;
+const DIRECTION_MASK: u32 = 0b111;
+const LOD_SHIFT: u32 = DIRECTION_SHIFT + DIRECTION_MASK.count_ones();
+const LOD_MASK: u32 = 0b11;
+
+const _: () = assert!(LOD_SHIFT + LOD_MASK.count_ones() <= u32::BITS);
+const _: () = assert!(LOD_LEVELS <= LOD_MASK as usize + 1);
+const _: () = assert!(texture::MAX_TEXTURES == TEXTURE_INDEX_MASK as usize + 1);
+
 #[derive(Debug, Copy, Clone, Pod, Zeroable)]
 #[repr(C)]
 pub struct CubeFaceInstance {
@@ -80,6 +153,27 @@ pub struct CubeFaceInstance {
     pub attributes: u32,
 }
 impl CubeFaceInstance {
+    /// Cube face of the block or cell at `position` inside of `chunk`. The shaders unpack the
+    /// attributes with the helpers in `instance.wgsl`.
+    pub fn new(
+        chunk: [i32; 3],
+        position: [u32; 3],
+        texture_index: u8,
+        direction: Direction,
+        lod: u32,
+    ) -> Self {
+        let [x, y, z] = position.map(|coordinate| coordinate & POSITION_MASK);
+        CubeFaceInstance {
+            chunk,
+            attributes: x
+                | (y << CHUNK_WIDTH_BITS)
+                | (z << (2 * CHUNK_WIDTH_BITS))
+                | ((texture_index as u32) << TEXTURE_INDEX_SHIFT)
+                | ((direction as u32) << DIRECTION_SHIFT)
+                | ((lod & LOD_MASK) << LOD_SHIFT),
+        }
+    }
+
     fn desc() -> VertexBufferLayout<'static> {
         VertexBufferLayout {
             array_stride: (mem::size_of::<[i32; 3]>() + mem::size_of::<u32>()) as BufferAddress,
@@ -100,62 +194,158 @@ impl CubeFaceInstance {
     }
 }
 
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+struct CameraUniform {
+    view_proj: [[f32; 4]; 4],
+    /// Camera position, w component is unused
+    position: [f32; 4],
+}
+impl CameraUniform {
+    fn new(camera_controller: &CameraController) -> Self {
+        CameraUniform {
+            view_proj: camera_controller
+                .get_view_projection_matrix()
+                .to_cols_array_2d(),
+            position: camera_controller.get_position().extend(1.0).to_array(),
+        }
+    }
+}
+
+/// Cube face instances of one loading job.
+struct ChunkInstances {
+    /// Faces of the chunks the camera can see
+    visible: Vec<CubeFaceInstance>,
+    /// Faces of all meshed chunks within the render distance, chunks hidden from the camera can
+    /// still cast shadows into view
+    shadow_casters: Vec<CubeFaceInstance>,
+}
+
+/// Vertex buffer of cube face instances that only grows.
+struct InstanceBuffer {
+    buffer: Buffer,
+    capacity: usize,
+    count: usize,
+    label: &'static str,
+}
+impl InstanceBuffer {
+    fn new(device: &Device, label: &'static str) -> Self {
+        // TODO use sensible default size, research `mapped_at_creation`
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some(label),
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            size: 0,
+            mapped_at_creation: false,
+        });
+        InstanceBuffer {
+            buffer,
+            capacity: 0,
+            count: 0,
+            label,
+        }
+    }
+
+    /// Replace the contents, recreating the buffer if they don't fit.
+    fn upload(&mut self, device: &Device, queue: &Queue, instances: &[CubeFaceInstance]) {
+        self.count = instances.len();
+        if instances.len() > self.capacity {
+            self.buffer.destroy();
+            self.buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some(self.label),
+                contents: bytemuck::cast_slice(instances),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            });
+            self.capacity = instances.len();
+        } else {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(instances));
+        }
+    }
+}
+
 pub struct WorldRenderer {
     device: Arc<Device>,
     queue: Arc<Queue>,
     vertex_buffer: Buffer,
-    instance_buffer: Buffer,
+    instance_buffer: InstanceBuffer,
+    /// Instances drawn into the shadow maps
+    shadow_instance_buffer: InstanceBuffer,
     pub camera_controller: CameraController,
+    pub environment: Environment,
     camera_uniform: Buffer,
     camera_bind_group: BindGroup,
+    texture_bind_group_layout: BindGroupLayout,
     texture_bind_group: BindGroup,
+    render_pipeline_layout: PipelineLayout,
+    color_format: TextureFormat,
     render_pipeline: RenderPipeline,
-    buffer_capacity: usize,
-    previous_camera_u: Option<i32>,
-    previous_camera_w: Option<i32>,
+    previous_camera_chunk: Option<(i32, i32, i32)>,
+    render_distance: i32,
     reticle_renderer: ui_renderer::Reticle,
+    /// Text and other overlays drawn on top of everything else
+    pub text_renderer: TextRenderer,
+    selection_outline: SelectionOutline,
+    sky_renderer: Sky,
+    pub cloud_renderer: Clouds,
+    shadows: Shadows,
+    gpu_profiler: GpuProfiler,
+    target: Option<RaycastHit>,
+    /// Loaded and meshed chunks when the world was last available
+    chunk_counts: (usize, usize),
+    /// Fraction of the targeted block that is broken, shown as cracks
+    pub break_progress: f32,
 
-    loading_thread_handle: Vec<JoinHandle<Vec<CubeFaceInstance>>>,
+    /// Most recently spawned loading thread, its results are uploaded once it finishes
+    loading_thread_handle: Option<JoinHandle<ChunkInstances>>,
+    /// Loading threads whose results are outdated, only kept to count them as pending jobs
+    outdated_loading_threads: Vec<JoinHandle<ChunkInstances>>,
+    /// Held by the loading thread for its whole run, so later threads don't generate the same
+    /// chunks again
+    loading_lock: Arc<Mutex<()>>,
+    hot_reload: Option<HotReload>,
+    /// `None` if only the embedded default pack is used
+    resource_pack_dir: Option<PathBuf>,
 }
 
 impl WorldRenderer {
     pub fn new(
         device: Arc<Device>,
         queue: Arc<Queue>,
-        surface_config: &SurfaceConfiguration,
-    ) -> Self {
+        color_format: TextureFormat,
+        size: (u32, u32),
+        resource_packs: &ResourcePacks,
+        world_seed: u32,
+    ) -> Result<Self> {
         let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("cube face vertex buffer"),
             contents: bytemuck::cast_slice(CUBE_FACE_VERTICES),
             usage: BufferUsages::VERTEX,
         });
 
-        // TODO use sensible default size, research `mapped_at_creation`
-        let instance_buffer = device.create_buffer(&BufferDescriptor {
-            label: Some("cube face instance buffer"),
-            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-            size: 0,
-            mapped_at_creation: false,
-        });
+        let instance_buffer = InstanceBuffer::new(&device, "cube face instance buffer");
+        let shadow_instance_buffer = InstanceBuffer::new(&device, "shadow caster instance buffer");
 
         let camera_controller = CameraController::new(
             glam::Vec3::NEG_X,
             -0.5,
             0.0,
             45.0,
-            surface_config.width as f32 / surface_config.height as f32,
-            0.1,
-            1000.0,
-            10.0,
+            size.0 as f32 / size.1 as f32,
             0.1,
+            WorldRenderer::view_distance(DEFAULT_RENDER_DISTANCE),
         );
 
         let camera_uniform = device.create_buffer_init(&BufferInitDescriptor {
             label: Some("camera uniform buffer"),
-            contents: bytemuck::cast_slice(&[camera_controller.get_view_projection_matrix()]),
+            contents: bytemuck::cast_slice(&[CameraUniform::new(&camera_controller)]),
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
 
+        let environment = Environment::new(
+            &device,
+            Color::BLACK,
+            WorldRenderer::fog_distance(DEFAULT_RENDER_DISTANCE),
+        );
+
         let camera_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[wgpu::BindGroupLayoutEntry {
@@ -182,32 +372,131 @@ impl WorldRenderer {
 
         let shader = device.create_shader_module(ShaderModuleDescriptor {
             label: Some("world shader"),
-            source: ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+            source: ShaderSource::Wgsl(
+                preprocessor::preprocess(include_str!("shader.wgsl")).into(),
+            ),
         });
 
-        let (texture_bind_group_layout, texture_bind_group) =
-            texture::load_textures(&device, &queue).unwrap();
+        let texture_bind_group_layout = texture::create_bind_group_layout(&device);
+        let texture_bind_group =
+            texture::load_textures(&device, &queue, &texture_bind_group_layout, resource_packs)?;
+
+        let shadows = Shadows::new(&device, ShadowQuality::High);
 
         let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("world render pipeline layout"),
-            bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
+            bind_group_layouts: &[
+                &camera_bind_group_layout,
+                environment.bind_group_layout(),
+                &texture_bind_group_layout,
+                shadows.bind_group_layout(),
+            ],
             push_constant_ranges: &[],
         });
 
-        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+        let render_pipeline = WorldRenderer::create_render_pipeline(
+            &device,
+            &render_pipeline_layout,
+            &shader,
+            color_format,
+        );
+
+        let sky_renderer = Sky::new(
+            &device,
+            &camera_bind_group_layout,
+            environment.bind_group_layout(),
+            color_format,
+        );
+
+        let cloud_renderer = Clouds::new(
+            &device,
+            &queue,
+            &camera_bind_group_layout,
+            environment.bind_group_layout(),
+            color_format,
+            world_seed,
+            CLOUD_HEIGHT,
+        );
+
+        let selection_outline =
+            SelectionOutline::new(&device, &camera_bind_group_layout, color_format);
+
+        let reticle_renderer = Reticle::new(&device, camera_bind_group_layout, color_format);
+        let text_renderer = TextRenderer::new(&device, &queue, color_format, size);
+        let gpu_profiler = GpuProfiler::new(&device, &queue);
+
+        // Packs that aren't loaded from a directory are meant to stay as they are
+        let hot_reload = match resource_packs.directory() {
+            Some(resource_pack_dir) if cfg!(debug_assertions) => {
+                let source_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+                HotReload::new(&[
+                    source_dir.join("src"),
+                    source_dir.join("res"),
+                    resource_pack_dir.to_owned(),
+                ])
+                .inspect_err(|error| log::warn!("Hot reloading unavailable: {error}"))
+                .ok()
+                .flatten()
+            }
+            _ => None,
+        };
+
+        Ok(WorldRenderer {
+            device,
+            queue,
+            vertex_buffer,
+            instance_buffer,
+            shadow_instance_buffer,
+            camera_controller,
+            environment,
+            camera_uniform,
+            camera_bind_group,
+            texture_bind_group_layout,
+            texture_bind_group,
+            render_pipeline_layout,
+            color_format,
+            render_pipeline,
+            previous_camera_chunk: None,
+            render_distance: DEFAULT_RENDER_DISTANCE,
+            reticle_renderer,
+            text_renderer,
+            selection_outline,
+            sky_renderer,
+            cloud_renderer,
+            shadows,
+            gpu_profiler,
+            target: None,
+            chunk_counts: (0, 0),
+            break_progress: 0.0,
+
+            loading_thread_handle: None,
+            outdated_loading_threads: Vec::new(),
+            loading_lock: Arc::new(Mutex::new(())),
+            hot_reload,
+            resource_pack_dir: resource_packs.directory().map(Path::to_owned),
+        })
+    }
+
+    fn create_render_pipeline(
+        device: &Device,
+        render_pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        color_format: TextureFormat,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
             label: Some("world render pipeline"),
-            layout: Some(&render_pipeline_layout),
+            layout: Some(render_pipeline_layout),
             vertex: VertexState {
-                module: &shader,
+                module: shader,
                 entry_point: "vs_main",
                 buffers: &[Vertex::desc(), CubeFaceInstance::desc()],
                 compilation_options: Default::default(),
             },
             fragment: Some(FragmentState {
-                module: &shader,
+                module: shader,
                 entry_point: "fs_main",
                 targets: &[Some(ColorTargetState {
-                    format: surface_config.format,
+                    format: color_format,
                     blend: Some(BlendState::REPLACE),
                     write_mask: ColorWrites::ALL,
                 })],
@@ -235,112 +524,232 @@ impl WorldRenderer {
                 alpha_to_coverage_enabled: false,
             },
             multiview: None,
-        });
+        })
+    }
 
-        let reticle_renderer =
-            Reticle::new(&device, camera_bind_group_layout, surface_config.format);
+    /// Reload changed shaders and textures, only active in debug builds.
+    fn hot_reload(&mut self) {
+        let Some(hot_reload) = &self.hot_reload else {
+            return;
+        };
 
-        WorldRenderer {
-            device,
-            queue,
-            vertex_buffer,
-            instance_buffer,
-            camera_controller,
-            camera_uniform,
-            camera_bind_group,
-            texture_bind_group,
-            render_pipeline,
-            buffer_capacity: 0,
-            previous_camera_u: None,
-            previous_camera_w: None,
-            reticle_renderer,
+        // Shaders which may use includes, relative to the crate root
+        const SHADER_FILES: [&str; 7] = [
+            "src/shader.wgsl",
+            "src/renderer/sky.wgsl",
+            "src/renderer/clouds.wgsl",
+            "src/renderer/shadow.wgsl",
+            "src/renderer/reticle.wgsl",
+            "src/renderer/selection.wgsl",
+            "src/renderer/text.wgsl",
+        ];
+
+        let mut shaders = HashSet::new();
+        let mut reload_textures = false;
+        for file in hot_reload.changed_files() {
+            match file.extension().and_then(OsStr::to_str) {
+                Some("wgsl") => {
+                    let name = file.file_name().and_then(OsStr::to_str).unwrap_or_default();
+                    if preprocessor::is_include(name) {
+                        let source_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+                        shaders.extend(SHADER_FILES.map(|shader| source_dir.join(shader)));
+                    } else {
+                        shaders.insert(file);
+                    }
+                }
+                Some("png" | "toml" | "zip") => reload_textures = true,
+                _ => {}
+            }
+        }
+
+        for file in shaders {
+            match fs::read_to_string(&file) {
+                Ok(source) => self.reload_shader(&file, &source),
+                Err(error) => log::error!("Failed to read {}: {error}", file.display()),
+            }
+        }
+
+        if reload_textures {
+            self.reload_textures();
+        }
+    }
 
-            loading_thread_handle: Vec::new(),
+    fn reload_shader(&mut self, file: &Path, source: &str) {
+        let device = &self.device;
+        match file.file_name().and_then(OsStr::to_str) {
+            Some("shader.wgsl") => {
+                if let Some(render_pipeline) =
+                    hot_reload::try_create_pipeline(device, "world shader", source, |shader| {
+                        WorldRenderer::create_render_pipeline(
+                            device,
+                            &self.render_pipeline_layout,
+                            shader,
+                            self.color_format,
+                        )
+                    })
+                {
+                    self.render_pipeline = render_pipeline;
+                }
+            }
+            Some("sky.wgsl") => self.sky_renderer.reload_shader(device, source),
+            Some("clouds.wgsl") => self.cloud_renderer.reload_shader(device, source),
+            Some("shadow.wgsl") => self.shadows.reload_shader(device, source),
+            Some("reticle.wgsl") => self.reticle_renderer.reload_shader(device, source),
+            Some("selection.wgsl") => self.selection_outline.reload_shader(device, source),
+            Some("text.wgsl") => self.text_renderer.reload_shader(device, source),
+            _ => {}
+        }
+    }
+
+    /// Reload all resource packs and rebuild the block textures.
+    fn reload_textures(&mut self) {
+        let Some(resource_pack_dir) = &self.resource_pack_dir else {
+            return;
+        };
+        let texture_bind_group = ResourcePacks::load(resource_pack_dir).and_then(|packs| {
+            texture::load_textures(
+                &self.device,
+                &self.queue,
+                &self.texture_bind_group_layout,
+                &packs,
+            )
+        });
+
+        match texture_bind_group {
+            Ok(texture_bind_group) => {
+                self.texture_bind_group = texture_bind_group;
+                log::info!("Reloaded textures");
+            }
+            Err(error) => log::error!("Failed to reload textures: {error:#}"),
         }
     }
 
     pub fn update(&mut self, world: Arc<Mutex<World>>) {
+        self.hot_reload();
+
         self.queue.write_buffer(
             &self.camera_uniform,
             0,
-            bytemuck::cast_slice(&[self.camera_controller.get_view_projection_matrix()]),
+            bytemuck::cast_slice(&[CameraUniform::new(&self.camera_controller)]),
+        );
+        self.text_renderer.update(&self.device, &self.queue);
+        self.gpu_profiler.poll(&self.device);
+        self.environment.update(&self.queue);
+        self.cloud_renderer.update(&self.queue);
+        self.shadows.update(
+            &self.queue,
+            self.camera_controller.get_position(),
+            self.environment.sun_direction,
+            self.environment.fog_end,
         );
 
-        if let Some(handle) = self.loading_thread_handle.pop() {
-            if handle.is_finished() {
-                let instances = handle.join().unwrap();
-                if instances.len() > self.buffer_capacity {
-                    self.instance_buffer.destroy();
-                    self.instance_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
-                        label: Some("cube face instance buffer"),
-                        contents: bytemuck::cast_slice(instances.as_slice()),
-                        usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
-                    });
-                    self.buffer_capacity = instances.len();
-                } else {
-                    self.queue.write_buffer(
-                        &self.instance_buffer,
-                        0,
-                        bytemuck::cast_slice(instances.as_slice()),
-                    );
-                }
-            } else {
-                self.loading_thread_handle.push(handle);
-            }
+        // Keep the previous target instead of waiting while the loading thread holds the lock
+        if let Ok(world) = world.try_lock() {
+            self.target = world.raycast(
+                self.camera_controller.get_position(),
+                self.camera_controller.get_direction(),
+                REACH_DISTANCE,
+            );
+            self.chunk_counts = (
+                world.chunk_columns.len() * VERTICAL_CHUNK_COUNT,
+                world.meshed_chunks.len(),
+            );
         }
+        self.selection_outline.update(
+            &self.queue,
+            self.target.map(|target| target.position),
+            self.break_progress,
+        );
 
-        let camera_u = self.camera_controller.get_position().x as i32 / CHUNK_DIMENSIONS;
-        let camera_w = self.camera_controller.get_position().z as i32 / CHUNK_DIMENSIONS;
+        if self
+            .loading_thread_handle
+            .as_ref()
+            .is_some_and(|handle| handle.is_finished())
+        {
+            let instances = self.loading_thread_handle.take().unwrap().join().unwrap();
+            self.instance_buffer
+                .upload(&self.device, &self.queue, &instances.visible);
+            self.shadow_instance_buffer.upload(
+                &self.device,
+                &self.queue,
+                &instances.shadow_casters,
+            );
+        }
+        self.outdated_loading_threads
+            .retain(|handle| !handle.is_finished());
 
-        if self.previous_camera_u.is_some_and(|u| u == camera_u)
-            && self.previous_camera_w.is_some_and(|w| w == camera_w)
+        let camera_chunk = self.get_camera_chunk();
+
+        if self
+            .previous_camera_chunk
+            .is_some_and(|chunk| chunk == camera_chunk)
         {
             return;
         }
 
-        self.previous_camera_u = Some(camera_u);
-        self.previous_camera_w = Some(camera_w);
-
-        let device_clone = Arc::clone(&self.device);
-        // let instance_buffer_clone = self.instance_buffer;
+        self.previous_camera_chunk = Some(camera_chunk);
+        let render_distance = self.render_distance;
+        let loading_lock = Arc::clone(&self.loading_lock);
 
         let handle = thread::spawn(move || {
-            let chunk_range_u =
-                camera_u - CHUNK_RENDER_DISTANCE..camera_u + CHUNK_RENDER_DISTANCE + 1;
-            let chunk_range_w =
-                camera_w - CHUNK_RENDER_DISTANCE..camera_w + CHUNK_RENDER_DISTANCE + 1;
-
-            let mut world_handle = world.lock().unwrap();
-
-            for u in chunk_range_u.clone() {
-                for w in chunk_range_w.clone() {
-                    // TODO error handling
-                    if world_handle.chunk_columns.get(&(u, w)).is_none() {
-                        world_handle.create_chunks(u, w);
-                    }
-                }
-            }
+            let _loading = loading_lock.lock().unwrap();
+            let (camera_u, _, camera_w) = camera_chunk;
+            let chunk_range_u = camera_u - render_distance..camera_u + render_distance + 1;
+            let chunk_range_w = camera_w - render_distance..camera_w + render_distance + 1;
 
-            let mut instances: Vec<&CubeFaceInstance> = Vec::new();
-            for u in chunk_range_u.clone() {
-                for w in chunk_range_w.clone() {
-                    let instances_ = (0..VERTICAL_CHUNK_COUNT)
-                        .flat_map(|v| world_handle.meshed_chunks.get(&(u, v as i32, w)).unwrap())
-                        .collect::<Vec<&CubeFaceInstance>>();
+            let (missing_columns, noise) = {
+                let mut world_handle = world.lock().unwrap();
+                world_handle.unload_chunks_outside(
+                    camera_u,
+                    camera_w,
+                    render_distance + UNLOAD_DISTANCE_MARGIN,
+                );
 
-                    instances.extend(instances_);
-                }
+                let missing_columns: Vec<(i32, i32)> = chunk_range_u
+                    .clone()
+                    .flat_map(|u| chunk_range_w.clone().map(move |w| (u, w)))
+                    .filter(|column| !world_handle.chunk_columns.contains_key(column))
+                    .collect();
+                (missing_columns, world_handle.terrain_noise())
+            };
+
+            // Generate without holding the lock, so the world stays available for the main thread
+            for (u, w) in missing_columns {
+                let column = World::generate_column(&noise, u, w);
+                world.lock().unwrap().insert_column(column);
             }
 
-            let instances: Vec<CubeFaceInstance> = instances
+            let world_handle = world.lock().unwrap();
+            let chunk_instances = |chunk @ &(u, _, w): &(i32, i32, i32)| {
+                let distance = (u - camera_u).abs().max((w - camera_w).abs());
+                let mesh = world_handle.meshed_chunks.get(chunk)?;
+                Some(mesh.instances[lod_for_distance(distance)].iter().copied())
+            };
+
+            // Skip chunks hidden behind solid terrain, e.g. caves below the surface
+            let visible = visibility::visible_chunks(&world_handle, camera_chunk, render_distance)
                 .iter()
-                .map(|instance| (*instance).to_owned())
+                .filter_map(chunk_instances)
+                .flatten()
                 .collect();
-
-            instances
+            let shadow_casters = chunk_range_u
+                .flat_map(|u| {
+                    let chunk_range_w = chunk_range_w.clone();
+                    (0..VERTICAL_CHUNK_COUNT as i32)
+                        .flat_map(move |v| chunk_range_w.clone().map(move |w| (u, v, w)))
+                })
+                .filter_map(|chunk| chunk_instances(&chunk))
+                .flatten()
+                .collect();
+            ChunkInstances {
+                visible,
+                shadow_casters,
+            }
         });
 
-        self.loading_thread_handle.push(handle);
+        // Results of threads spawned earlier are outdated
+        self.outdated_loading_threads
+            .extend(self.loading_thread_handle.replace(handle));
 
         // let chunk_range_u = camera_u - CHUNK_RENDER_DISTANCE..camera_u + CHUNK_RENDER_DISTANCE + 1;
         // let chunk_range_w = camera_w - CHUNK_RENDER_DISTANCE..camera_w + CHUNK_RENDER_DISTANCE + 1;
@@ -392,19 +801,343 @@ impl WorldRenderer {
         // }
     }
 
-    pub fn render<'a: 'b, 'b>(&'a self, render_pass: &mut RenderPass<'b>) {
+    /// Update until the chunks around the camera are loaded and uploaded, for rendering single
+    /// frames of a fully loaded world.
+    pub fn wait_for_chunks(&mut self, world: Arc<Mutex<World>>) {
+        self.update(Arc::clone(&world));
+        while self.loading_thread_handle.is_some() {
+            thread::sleep(Duration::from_millis(10));
+            self.update(Arc::clone(&world));
+        }
+    }
+
+    /// Rebuild the instance buffer from the world's chunk meshes on the next update, e.g. after
+    /// blocks changed.
+    pub fn invalidate_meshes(&mut self) {
+        self.previous_camera_chunk = None;
+    }
+
+    /// Coordinates of the chunk containing the camera.
+    pub fn get_camera_chunk(&self) -> (i32, i32, i32) {
+        let camera_position = self.camera_controller.get_position();
+        (
+            (camera_position.x / CHUNK_DIMENSIONS as f32).floor() as i32,
+            (camera_position.y / CHUNK_DIMENSIONS as f32).floor() as i32,
+            (camera_position.z / CHUNK_DIMENSIONS as f32).floor() as i32,
+        )
+    }
+
+    /// Block the camera is looking at within reach.
+    pub fn get_target(&self) -> Option<RaycastHit> {
+        self.target
+    }
+
+    /// Number of loaded and of meshed chunks.
+    pub fn get_chunk_counts(&self) -> (usize, usize) {
+        self.chunk_counts
+    }
+
+    /// Number of cube faces in the instance buffer.
+    pub fn get_instance_count(&self) -> usize {
+        self.instance_buffer.count
+    }
+
+    /// Number of chunk loading and meshing jobs that are still running or whose results haven't
+    /// been uploaded yet.
+    pub fn get_pending_jobs(&self) -> usize {
+        self.outdated_loading_threads.len() + usize::from(self.loading_thread_handle.is_some())
+    }
+
+    /// GPU time of the passes of a recent frame, if timestamp queries are supported.
+    pub fn get_gpu_timings(&self) -> Option<&GpuTimings> {
+        self.gpu_profiler.timings()
+    }
+
+    /// Adapt to a new render target size in pixels.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.camera_controller
+            .set_aspect_ratio(width as f32 / height as f32);
+        self.text_renderer
+            .set_screen_size(&self.queue, (width, height));
+    }
+
+    pub fn get_render_distance(&self) -> i32 {
+        self.render_distance
+    }
+
+    /// Change the render distance in chunks. Chunks are loaded, unloaded and remeshed on the next
+    /// update.
+    pub fn set_render_distance(&mut self, render_distance: i32) {
+        let render_distance = render_distance.clamp(MIN_RENDER_DISTANCE, MAX_RENDER_DISTANCE);
+        if render_distance == self.render_distance {
+            return;
+        }
+
+        log::info!("Render distance set to {render_distance} chunks");
+        self.render_distance = render_distance;
+        self.camera_controller
+            .set_z_far(WorldRenderer::view_distance(render_distance));
+        self.environment.fog_end = WorldRenderer::fog_distance(render_distance);
+        // Force rebuilding the instance buffer
+        self.previous_camera_chunk = None;
+    }
+
+    pub fn get_shadow_quality(&self) -> ShadowQuality {
+        self.shadows.get_quality()
+    }
+
+    pub fn set_shadow_quality(&mut self, quality: ShadowQuality) {
+        log::info!("Shadow quality: {quality:?}");
+        self.shadows.set_quality(&self.device, quality);
+    }
+
+    /// Distance from the camera to the farthest visible block for a given render distance.
+    fn view_distance(render_distance: i32) -> f32 {
+        let horizontal = ((render_distance + 1) * CHUNK_DIMENSIONS) as f32;
+        (2.0 * horizontal * horizontal + (WORLD_HEIGHT * WORLD_HEIGHT) as f32).sqrt()
+    }
+
+    /// Terrain is completely hidden by fog at this distance, so the border of the loaded area is
+    /// never visible.
+    fn fog_distance(render_distance: i32) -> f32 {
+        (render_distance * CHUNK_DIMENSIONS) as f32
+    }
+
+    /// Render a frame into `view`, using `depth_view` as depth buffer of the same size.
+    pub fn render_frame(
+        &self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        depth_view: &TextureView,
+    ) {
+        self.render_shadows(encoder);
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("sky render pass"),
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(self.environment.fog_color),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: self.gpu_profiler.timestamp_writes(ProfiledPass::Sky),
+            });
+
+            self.render_sky(&mut render_pass);
+        }
+
+        {
+            let mut render_pass = self.begin_scene_pass(
+                encoder,
+                ProfiledPass::World,
+                view,
+                depth_view,
+                LoadOp::Clear(1.0),
+            );
+            self.render(&mut render_pass);
+        }
+
+        {
+            let mut render_pass = self.begin_scene_pass(
+                encoder,
+                ProfiledPass::Translucent,
+                view,
+                depth_view,
+                LoadOp::Load,
+            );
+            self.render_translucent(&mut render_pass);
+        }
+
+        {
+            let mut render_pass =
+                self.begin_scene_pass(encoder, ProfiledPass::Ui, view, depth_view, LoadOp::Load);
+            self.render_ui(&mut render_pass);
+        }
+    }
+
+    /// Begin a render pass drawing on top of `view`, with `depth_view` as depth buffer.
+    fn begin_scene_pass<'a>(
+        &'a self,
+        encoder: &'a mut CommandEncoder,
+        pass: ProfiledPass,
+        view: &'a TextureView,
+        depth_view: &'a TextureView,
+        depth_load: LoadOp<f32>,
+    ) -> RenderPass<'a> {
+        encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some(&format!("{} render pass", pass.name())),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(Operations {
+                    load: depth_load,
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: self.gpu_profiler.timestamp_writes(pass),
+        })
+    }
+
+    /// Submit a frame encoded with `render_frame`, reading back its GPU timings if profiling.
+    pub fn submit_frame(&mut self, mut encoder: CommandEncoder) {
+        let passes: Vec<ProfiledPass> = ProfiledPass::ALL
+            .into_iter()
+            .filter(|pass| {
+                *pass != ProfiledPass::Shadows || self.get_shadow_quality() != ShadowQuality::Off
+            })
+            .collect();
+        self.gpu_profiler.resolve(&mut encoder, &passes);
+        self.queue.submit([encoder.finish()]);
+        self.gpu_profiler.map_results();
+    }
+
+    /// Render a frame of the given size offscreen and read it back.
+    pub fn screenshot(&self, width: u32, height: u32) -> Result<RgbaImage> {
+        let target = OffscreenTarget::new(&self.device, self.color_format, width, height);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor {
+                label: Some("screenshot encoder"),
+            });
+        self.render_frame(&mut encoder, target.color_view(), target.depth_view());
+        self.queue.submit([encoder.finish()]);
+
+        target.read_image(&self.device, &self.queue)
+    }
+
+    /// Render the shadow maps, has to happen before the world is rendered.
+    fn render_shadows(&self, encoder: &mut CommandEncoder) {
+        self.shadows.render(
+            encoder,
+            &self.vertex_buffer,
+            &self.shadow_instance_buffer.buffer,
+            CUBE_FACE_VERTICES.len() as u32,
+            self.shadow_instance_buffer.count as u32,
+            self.gpu_profiler.timestamp_writes(ProfiledPass::Shadows),
+        );
+    }
+
+    /// Draw the sky, expects a render pass without depth attachment.
+    fn render_sky<'a: 'b, 'b>(&'a self, render_pass: &mut RenderPass<'b>) {
+        self.sky_renderer.render(
+            render_pass,
+            &self.camera_bind_group,
+            self.environment.bind_group(),
+        );
+    }
+
+    fn render<'a: 'b, 'b>(&'a self, render_pass: &mut RenderPass<'b>) {
         render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.texture_bind_group, &[]);
-        render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, self.environment.bind_group(), &[]);
+        render_pass.set_bind_group(2, &self.texture_bind_group, &[]);
+        render_pass.set_bind_group(3, self.shadows.bind_group(), &[]);
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.buffer.slice(..));
 
         render_pass.draw(
             0..CUBE_FACE_VERTICES.len() as u32,
-            0..self.buffer_capacity as u32,
+            0..self.instance_buffer.count as u32,
         );
+    }
 
+    /// Draw translucent geometry, after all opaque geometry.
+    fn render_translucent<'a: 'b, 'b>(&'a self, render_pass: &mut RenderPass<'b>) {
+        self.cloud_renderer.render(
+            render_pass,
+            &self.camera_bind_group,
+            self.environment.bind_group(),
+        );
+
+        self.selection_outline
+            .render(render_pass, &self.camera_bind_group);
+    }
+
+    /// Draw the UI on top of everything else.
+    fn render_ui<'a: 'b, 'b>(&'a self, render_pass: &mut RenderPass<'b>) {
         self.reticle_renderer
             .render(render_pass, &self.camera_bind_group);
+
+        self.text_renderer.render(render_pass);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unpack(instance: &CubeFaceInstance) -> ([u32; 3], u8, u32, u32) {
+        let bits = instance.attributes;
+        let position = [
+            bits & POSITION_MASK,
+            (bits >> CHUNK_WIDTH_BITS) & POSITION_MASK,
+            (bits >> (2 * CHUNK_WIDTH_BITS)) & POSITION_MASK,
+        ];
+        let texture_index = ((bits >> TEXTURE_INDEX_SHIFT) & TEXTURE_INDEX_MASK) as u8;
+        let direction = (bits >> DIRECTION_SHIFT) & DIRECTION_MASK;
+        let lod = (bits >> LOD_SHIFT) & LOD_MASK;
+        (position, texture_index, direction, lod)
+    }
+
+    #[test]
+    fn instance_packing_round_trips() {
+        let max_position = CHUNK_DIMENSIONS as u32 - 1;
+        for position in [
+            [0, 0, 0],
+            [1, 2, 3],
+            [max_position; 3],
+            [max_position, 0, 17],
+        ] {
+            for texture_index in [0, 1, 127, u8::MAX] {
+                for direction in Direction::ALL {
+                    for lod in 0..LOD_LEVELS as u32 {
+                        let instance = CubeFaceInstance::new(
+                            [-3, 5, i32::MIN],
+                            position,
+                            texture_index,
+                            direction,
+                            lod,
+                        );
+                        assert_eq!(instance.chunk, [-3, 5, i32::MIN]);
+                        assert_eq!(
+                            unpack(&instance),
+                            (position, texture_index, direction as u32, lod)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn instance_fields_dont_overlap() {
+        let fields = [
+            POSITION_MASK,
+            POSITION_MASK << CHUNK_WIDTH_BITS,
+            POSITION_MASK << (2 * CHUNK_WIDTH_BITS),
+            TEXTURE_INDEX_MASK << TEXTURE_INDEX_SHIFT,
+            DIRECTION_MASK << DIRECTION_SHIFT,
+            LOD_MASK << LOD_SHIFT,
+        ];
+        for (i, a) in fields.iter().enumerate() {
+            for b in &fields[i + 1..] {
+                assert_eq!(a & b, 0);
+            }
+        }
     }
 }
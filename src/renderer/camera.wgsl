@@ -0,0 +1,10 @@
+// Camera uniform shared by all shaders drawing in world space. The camera bind group is always
+// bound to group 0.
+
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    position: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
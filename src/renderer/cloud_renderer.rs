@@ -0,0 +1,300 @@
+use std::f64::consts::TAU;
+
+use bytemuck::{Pod, Zeroable};
+use noise::{NoiseFn, Simplex};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendState,
+    Buffer, BufferBindingType, BufferUsages, ColorTargetState, ColorWrites, CompareFunction,
+    DepthBiasState, DepthStencilState, Device, Extent3d, FilterMode, FragmentState, FrontFace,
+    ImageDataLayout, MultisampleState, PipelineLayout, PipelineLayoutDescriptor, PolygonMode,
+    PrimitiveState, PrimitiveTopology, Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor,
+    SamplerBindingType, SamplerDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages, StencilState, TextureDescriptor, TextureDimension, TextureFormat,
+    TextureSampleType, TextureUsages, TextureViewDescriptor, TextureViewDimension, VertexState,
+};
+
+use crate::renderer::{hot_reload, preprocessor::preprocess};
+
+/// Side length of the cloud texture in texels
+const CLOUD_TEXTURE_SIZE: u32 = 256;
+/// Side length of a single cloud texel in blocks
+const CLOUD_CELL_SIZE: f32 = 12.0;
+/// Distance the clouds drift along X in blocks per second
+const CLOUD_SPEED: f32 = 2.0;
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+struct CloudUniform {
+    height: f32,
+    cell_size: f32,
+    texture_size: f32,
+    speed: f32,
+}
+
+impl CloudUniform {
+    fn new(height: f32) -> Self {
+        CloudUniform {
+            height,
+            cell_size: CLOUD_CELL_SIZE,
+            texture_size: CLOUD_TEXTURE_SIZE as f32,
+            speed: CLOUD_SPEED,
+        }
+    }
+}
+
+/// Renders a flat layer of clouds above the terrain.
+pub struct Clouds {
+    /// Altitude of the cloud layer in blocks
+    pub height: f32,
+    uniform: Buffer,
+    bind_group: BindGroup,
+    render_pipeline_layout: PipelineLayout,
+    color_format: TextureFormat,
+    render_pipeline: RenderPipeline,
+}
+
+impl Clouds {
+    /// Clouds at an altitude of `height` blocks, shaped by `seed`.
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        camera_bind_group_layout: &BindGroupLayout,
+        environment_bind_group_layout: &BindGroupLayout,
+        color_format: TextureFormat,
+        seed: u32,
+        height: f32,
+    ) -> Self {
+        let uniform = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("cloud uniform buffer"),
+            contents: bytemuck::cast_slice(&[CloudUniform::new(height)]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let size = Extent3d {
+            width: CLOUD_TEXTURE_SIZE,
+            height: CLOUD_TEXTURE_SIZE,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("cloud texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            &Clouds::generate_coverage(seed),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(CLOUD_TEXTURE_SIZE),
+                rows_per_image: Some(CLOUD_TEXTURE_SIZE),
+            },
+            size,
+        );
+        let texture_view = texture.create_view(&TextureViewDescriptor::default());
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("cloud sampler"),
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("cloud bind group layout"),
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&texture_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("cloud bind group"),
+        });
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("cloud shader"),
+            source: ShaderSource::Wgsl(preprocess(include_str!("clouds.wgsl")).into()),
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("cloud render pipeline layout"),
+            bind_group_layouts: &[
+                camera_bind_group_layout,
+                environment_bind_group_layout,
+                &bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline =
+            Clouds::create_render_pipeline(device, &render_pipeline_layout, &shader, color_format);
+
+        Clouds {
+            height,
+            uniform,
+            bind_group,
+            render_pipeline_layout,
+            color_format,
+            render_pipeline,
+        }
+    }
+
+    fn create_render_pipeline(
+        device: &Device,
+        render_pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        color_format: TextureFormat,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("cloud render pipeline"),
+            layout: Some(render_pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: color_format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Cw,
+                // Clouds are visible from above and below
+                cull_mode: None,
+                polygon_mode: PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                // Translucent, so don't hide anything rendered afterwards
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::Less,
+                stencil: StencilState::default(),
+                bias: DepthBiasState::default(),
+            }),
+            multisample: MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    /// Rebuild the render pipeline from new shader source, keeping the old one on errors.
+    pub fn reload_shader(&mut self, device: &Device, source: &str) {
+        if let Some(render_pipeline) =
+            hot_reload::try_create_pipeline(device, "cloud shader", source, |shader| {
+                Clouds::create_render_pipeline(
+                    device,
+                    &self.render_pipeline_layout,
+                    shader,
+                    self.color_format,
+                )
+            })
+        {
+            self.render_pipeline = render_pipeline;
+        }
+    }
+
+    /// Generate a seamlessly tiling cloud coverage map, one byte per texel.
+    fn generate_coverage(seed: u32) -> Vec<u8> {
+        let noise = Simplex::new(seed);
+        let mut coverage = Vec::with_capacity((CLOUD_TEXTURE_SIZE * CLOUD_TEXTURE_SIZE) as usize);
+
+        for y in 0..CLOUD_TEXTURE_SIZE {
+            for x in 0..CLOUD_TEXTURE_SIZE {
+                // Sample 4D noise on a torus, so opposing texture edges match
+                let (x_sin, x_cos) = (x as f64 / CLOUD_TEXTURE_SIZE as f64 * TAU).sin_cos();
+                let (y_sin, y_cos) = (y as f64 / CLOUD_TEXTURE_SIZE as f64 * TAU).sin_cos();
+                let point = [x_sin, x_cos, y_sin, y_cos];
+
+                let value =
+                    noise.get(point.map(|c| 1.5 * c)) + 0.5 * noise.get(point.map(|c| 4.0 * c));
+                coverage.push(if value > 0.2 { u8::MAX } else { 0 });
+            }
+        }
+
+        coverage
+    }
+
+    /// Upload the current settings to the uniform buffer.
+    pub fn update(&self, queue: &Queue) {
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::cast_slice(&[CloudUniform::new(self.height)]),
+        );
+    }
+
+    pub fn render<'a: 'b, 'b>(
+        &'a self,
+        render_pass: &mut RenderPass<'b>,
+        camera_bind_group: &'b BindGroup,
+        environment_bind_group: &'b BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, environment_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
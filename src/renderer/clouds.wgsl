@@ -0,0 +1,68 @@
+#include "camera.wgsl"
+#include "environment.wgsl"
+#include "fog.wgsl"
+
+struct CloudUniform {
+    height: f32,
+    // Side length of a single cloud texel in blocks
+    cell_size: f32,
+    // Side length of the cloud texture in texels
+    texture_size: f32,
+    // Drift along X in blocks per second
+    speed: f32,
+};
+
+@group(2) @binding(0)
+var<uniform> clouds: CloudUniform;
+@group(2) @binding(1)
+var t_clouds: texture_2d<f32>;
+@group(2) @binding(2)
+var s_clouds: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    // Position relative to the camera
+    @location(1) camera_offset: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32
+) -> VertexOutput {
+    // Horizontal quad centered above the camera, reaching up to the fog end distance
+    var corners = array<vec2f, 6>(
+        vec2f(-1.0, -1.0),
+        vec2f(-1.0, 1.0),
+        vec2f(1.0, -1.0),
+        vec2f(1.0, -1.0),
+        vec2f(-1.0, 1.0),
+        vec2f(1.0, 1.0),
+    );
+    let corner = environment.fog_end * corners[vertex_index] + camera.position.xz;
+    let world_position = vec3f(corner.x, clouds.height, corner.y);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4f(world_position, 1.0);
+    out.world_position = world_position;
+    out.camera_offset = world_position - camera.position.xyz;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // The texture repeats, so only the fraction of a texture width matters. Wrapping keeps the
+    // offset precise as the time grows.
+    let texture_width = clouds.cell_size * clouds.texture_size;
+    let scroll = fract(environment.time * clouds.speed / texture_width);
+    let texture_coordinates = in.world_position.xz / texture_width + vec2f(scroll, 0.0);
+    let coverage = textureSample(t_clouds, s_clouds, texture_coordinates).r;
+    if coverage < 0.5 {
+        discard;
+    }
+
+    let brightness = clamp(environment.ambient_light + environment.sun_intensity, 0.0, 1.0);
+    let fog = fog_factor(in.camera_offset);
+    let color = mix(vec3f(brightness), environment.fog_color.rgb, fog);
+    return vec4f(color, 0.8 * (1.0 - fog));
+}
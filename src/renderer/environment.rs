@@ -0,0 +1,175 @@
+use bytemuck::{Pod, Zeroable};
+use glam::Vec3;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferBindingType, BufferUsages, Color, Device,
+    Queue, ShaderStages,
+};
+
+use crate::world::time::WorldTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FogMode {
+    Off,
+    /// Fog increases linearly between the fog start and the fog end distance
+    Linear,
+    /// Fog increases exponentially with the distance and is nearly opaque at the fog end distance
+    Exponential,
+}
+
+impl FogMode {
+    pub fn next(&self) -> FogMode {
+        match self {
+            FogMode::Off => FogMode::Linear,
+            FogMode::Linear => FogMode::Exponential,
+            FogMode::Exponential => FogMode::Off,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+struct EnvironmentUniform {
+    fog_color: [f32; 4],
+    fog_start: f32,
+    fog_end: f32,
+    fog_density: f32,
+    fog_mode: u32,
+    sun_direction: [f32; 3],
+    sun_intensity: f32,
+    ambient_light: f32,
+    star_visibility: f32,
+    time_of_day: f32,
+    time: f32,
+    zenith_color: [f32; 4],
+}
+
+/// Scene wide rendering parameters shared by the world shaders, bound as a single uniform buffer.
+pub struct Environment {
+    pub fog_mode: FogMode,
+    pub fog_color: Color,
+    /// Horizontal distance in blocks at which the fog completely hides the terrain
+    pub fog_end: f32,
+    /// Normalized direction pointing towards the sun
+    pub sun_direction: Vec3,
+    pub sun_intensity: f32,
+    pub ambient_light: f32,
+    /// Sky color straight above the camera, the sky blends into the fog color at the horizon
+    pub zenith_color: Color,
+    /// Between 0.0 (invisible) and 1.0 (fully visible)
+    pub star_visibility: f32,
+    /// Fraction of the current day, used to rotate the stars across the sky
+    pub time_of_day: f32,
+    /// Seconds since startup, drives texture animations
+    pub time: f32,
+    uniform: Buffer,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+}
+
+impl Environment {
+    /// Fraction of the fog end distance at which linear fog starts
+    const LINEAR_FOG_START: f32 = 0.6;
+
+    pub fn new(device: &Device, fog_color: Color, fog_end: f32) -> Self {
+        let uniform = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("environment uniform buffer"),
+            contents: bytemuck::cast_slice(&[EnvironmentUniform::zeroed()]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("environment bind group layout"),
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            }],
+            label: Some("environment bind group"),
+        });
+
+        Environment {
+            fog_mode: FogMode::Linear,
+            fog_color,
+            fog_end,
+            sun_direction: Vec3::Y,
+            sun_intensity: 1.0,
+            ambient_light: 0.4,
+            zenith_color: fog_color,
+            star_visibility: 0.0,
+            time_of_day: 0.0,
+            time: 0.0,
+            uniform,
+            bind_group_layout,
+            bind_group,
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    /// Light the scene and color the sky and fog for the time of day of `world_time`.
+    pub fn set_world_time(&mut self, world_time: &WorldTime) {
+        // Blend the terrain into the sky at the render distance
+        self.fog_color = to_color(world_time.sky_color());
+        self.sun_direction = world_time.sun_direction();
+        self.sun_intensity = world_time.sun_intensity();
+        self.ambient_light = world_time.ambient_light();
+        self.zenith_color = to_color(world_time.zenith_color());
+        self.star_visibility = world_time.star_visibility();
+        self.time_of_day = world_time.get_time_of_day();
+    }
+
+    /// Upload the current settings to the uniform buffer.
+    pub fn update(&self, queue: &Queue) {
+        let uniform = EnvironmentUniform {
+            fog_color: color_to_array(self.fog_color),
+            fog_start: Self::LINEAR_FOG_START * self.fog_end,
+            fog_end: self.fog_end,
+            // exp(-4) is below 2%, so the fog is nearly opaque at the fog end distance
+            fog_density: 4.0 / self.fog_end,
+            fog_mode: self.fog_mode as u32,
+            sun_direction: self.sun_direction.to_array(),
+            sun_intensity: self.sun_intensity,
+            ambient_light: self.ambient_light,
+            star_visibility: self.star_visibility,
+            time_of_day: self.time_of_day,
+            time: self.time,
+            zenith_color: color_to_array(self.zenith_color),
+        };
+
+        queue.write_buffer(&self.uniform, 0, bytemuck::cast_slice(&[uniform]));
+    }
+}
+
+fn color_to_array(color: Color) -> [f32; 4] {
+    [color.r as f32, color.g as f32, color.b as f32, 1.0]
+}
+
+fn to_color(rgb: Vec3) -> Color {
+    Color {
+        r: rgb.x as f64,
+        g: rgb.y as f64,
+        b: rgb.z as f64,
+        a: 0.0,
+    }
+}
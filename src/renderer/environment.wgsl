@@ -0,0 +1,21 @@
+// Time of day, lighting and fog, mirrors `EnvironmentUniform` in environment.rs. The environment
+// bind group is always bound to group 1.
+
+struct EnvironmentUniform {
+    fog_color: vec4<f32>,
+    fog_start: f32,
+    fog_end: f32,
+    fog_density: f32,
+    // 0: off, 1: linear, 2: exponential
+    fog_mode: u32,
+    sun_direction: vec3<f32>,
+    sun_intensity: f32,
+    ambient_light: f32,
+    star_visibility: f32,
+    time_of_day: f32,
+    time: f32,
+    zenith_color: vec4<f32>,
+};
+
+@group(1) @binding(0)
+var<uniform> environment: EnvironmentUniform;
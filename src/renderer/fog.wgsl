@@ -0,0 +1,24 @@
+// Distance fog shared by the terrain and the clouds
+
+#include "environment.wgsl"
+
+// Returns how much the fog hides a fragment, between 0.0 (not at all) and 1.0 (completely)
+fn fog_factor(camera_offset: vec3<f32>) -> f32 {
+    // Only the horizontal distance matters, the fog hides the border of the loaded area
+    let distance = length(camera_offset.xz);
+    switch environment.fog_mode {
+        case 1u: {
+            return clamp(
+                (distance - environment.fog_start) / (environment.fog_end - environment.fog_start),
+                0.0,
+                1.0,
+            );
+        }
+        case 2u: {
+            return 1.0 - exp(-environment.fog_density * distance);
+        }
+        default: {
+            return 0.0;
+        }
+    }
+}
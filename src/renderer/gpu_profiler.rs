@@ -0,0 +1,293 @@
+use std::{
+    sync::mpsc::{self, Receiver, TryRecvError},
+    time::{Duration, Instant},
+};
+
+use wgpu::{
+    Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, CommandEncoder, Device, Features,
+    Maintain, MapMode, QuerySet, QuerySetDescriptor, QueryType, Queue, RenderPassTimestampWrites,
+    QUERY_SIZE,
+};
+
+/// Timings are logged at most this often
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Groups of render passes timed by the profiler, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfiledPass {
+    Shadows,
+    Sky,
+    World,
+    /// Clouds and the selection outline
+    Translucent,
+    /// Reticle and text
+    Ui,
+}
+
+impl ProfiledPass {
+    pub const ALL: [ProfiledPass; 5] = [
+        ProfiledPass::Shadows,
+        ProfiledPass::Sky,
+        ProfiledPass::World,
+        ProfiledPass::Translucent,
+        ProfiledPass::Ui,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProfiledPass::Shadows => "shadows",
+            ProfiledPass::Sky => "sky",
+            ProfiledPass::World => "world",
+            ProfiledPass::Translucent => "translucent",
+            ProfiledPass::Ui => "ui",
+        }
+    }
+
+    /// Indices of the timestamps written at the beginning and end of the pass.
+    fn query_indices(&self) -> (u32, u32) {
+        let index = 2 * *self as u32;
+        (index, index + 1)
+    }
+}
+
+const QUERY_COUNT: u32 = 2 * ProfiledPass::ALL.len() as u32;
+
+/// GPU time of each pass of a frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuTimings {
+    /// Passes that didn't run in the profiled frame are missing
+    pub passes: Vec<(ProfiledPass, Duration)>,
+}
+
+impl GpuTimings {
+    /// Turn raw timestamps into durations, using `period` nanoseconds per timestamp tick.
+    fn from_timestamps(timestamps: &[u64], passes: &[ProfiledPass], period: f32) -> Self {
+        let passes = passes
+            .iter()
+            .filter_map(|pass| {
+                let (beginning, end) = pass.query_indices();
+                // Timestamps can go backwards, e.g. when the GPU changes its clock
+                let ticks = timestamps[end as usize].checked_sub(timestamps[beginning as usize])?;
+                Some((
+                    *pass,
+                    Duration::from_nanos((ticks as f64 * period as f64) as u64),
+                ))
+            })
+            .collect();
+        GpuTimings { passes }
+    }
+
+    pub fn total(&self) -> Duration {
+        self.passes.iter().map(|(_, duration)| *duration).sum()
+    }
+}
+
+/// Readback of the timestamps of one frame, while it's in flight.
+enum Readback {
+    Idle,
+    /// Copied to the readback buffer by the frame's commands, which have to be submitted before
+    /// mapping it
+    Copied(Vec<ProfiledPass>),
+    Mapping(Vec<ProfiledPass>, Receiver<Result<(), BufferAsyncError>>),
+}
+
+struct Queries {
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f32,
+    readback: Readback,
+}
+
+/// Measures the GPU time of render passes with timestamp queries. Results arrive a few frames
+/// late, since they are read back without waiting for the GPU. Frames rendered while the previous
+/// results are still in flight aren't measured.
+///
+/// Does nothing if the device doesn't support `Features::TIMESTAMP_QUERY`.
+pub struct GpuProfiler {
+    queries: Option<Queries>,
+    timings: Option<GpuTimings>,
+    last_log: Instant,
+}
+
+impl GpuProfiler {
+    pub fn new(device: &Device, queue: &Queue) -> Self {
+        let queries = if device.features().contains(Features::TIMESTAMP_QUERY) {
+            let buffer_size = QUERY_COUNT as u64 * QUERY_SIZE as u64;
+            Some(Queries {
+                query_set: device.create_query_set(&QuerySetDescriptor {
+                    label: Some("pass timestamp query set"),
+                    ty: QueryType::Timestamp,
+                    count: QUERY_COUNT,
+                }),
+                resolve_buffer: device.create_buffer(&BufferDescriptor {
+                    label: Some("timestamp resolve buffer"),
+                    size: buffer_size,
+                    usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                }),
+                readback_buffer: device.create_buffer(&BufferDescriptor {
+                    label: Some("timestamp readback buffer"),
+                    size: buffer_size,
+                    usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                }),
+                timestamp_period: queue.get_timestamp_period(),
+                readback: Readback::Idle,
+            })
+        } else {
+            log::info!("GPU profiling unavailable: timestamp queries are not supported");
+            None
+        };
+
+        GpuProfiler {
+            queries,
+            timings: None,
+            last_log: Instant::now(),
+        }
+    }
+
+    /// Timestamp writes measuring `pass`, if supported. Passes split into several render passes
+    /// write the beginning with their first and the end with their last one.
+    pub fn timestamp_writes(&self, pass: ProfiledPass) -> Option<RenderPassTimestampWrites<'_>> {
+        let queries = self.queries.as_ref()?;
+        let (beginning, end) = pass.query_indices();
+        Some(RenderPassTimestampWrites {
+            query_set: &queries.query_set,
+            beginning_of_pass_write_index: Some(beginning),
+            end_of_pass_write_index: Some(end),
+        })
+    }
+
+    /// Copy the timestamps of the frame encoded with `encoder` for reading them back, unless the
+    /// previous ones are still in flight. Only the timestamps of `passes` are valid.
+    pub fn resolve(&mut self, encoder: &mut CommandEncoder, passes: &[ProfiledPass]) {
+        let Some(queries) = &mut self.queries else {
+            return;
+        };
+        if !matches!(queries.readback, Readback::Idle) {
+            return;
+        }
+
+        encoder.resolve_query_set(
+            &queries.query_set,
+            0..QUERY_COUNT,
+            &queries.resolve_buffer,
+            0,
+        );
+        encoder.copy_buffer_to_buffer(
+            &queries.resolve_buffer,
+            0,
+            &queries.readback_buffer,
+            0,
+            queries.resolve_buffer.size(),
+        );
+        queries.readback = Readback::Copied(passes.to_vec());
+    }
+
+    /// Start reading back the resolved timestamps, has to be called after submitting the frame.
+    pub fn map_results(&mut self) {
+        let Some(queries) = &mut self.queries else {
+            return;
+        };
+        let Readback::Copied(passes) = &mut queries.readback else {
+            return;
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        queries
+            .readback_buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                // The receiver is gone if the profiler was dropped in the meantime
+                let _ = sender.send(result);
+            });
+        queries.readback = Readback::Mapping(std::mem::take(passes), receiver);
+    }
+
+    /// Collect the timestamps once they have been read back, without blocking.
+    pub fn poll(&mut self, device: &Device) {
+        let Some(queries) = &mut self.queries else {
+            return;
+        };
+        let Readback::Mapping(passes, receiver) = &queries.readback else {
+            return;
+        };
+
+        device.poll(Maintain::Poll);
+        match receiver.try_recv() {
+            Ok(Ok(())) => {
+                let timings = {
+                    let mapped = queries.readback_buffer.slice(..).get_mapped_range();
+                    let timestamps: &[u64] = bytemuck::cast_slice(&mapped);
+                    GpuTimings::from_timestamps(timestamps, passes, queries.timestamp_period)
+                };
+                queries.readback_buffer.unmap();
+                queries.readback = Readback::Idle;
+
+                if self.last_log.elapsed() >= LOG_INTERVAL {
+                    self.last_log = Instant::now();
+                    log::info!("GPU timings: {}", format_timings(&timings));
+                }
+                self.timings = Some(timings);
+            }
+            Ok(Err(error)) => {
+                log::error!("Failed to read back GPU timestamps: {error}");
+                queries.readback = Readback::Idle;
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => queries.readback = Readback::Idle,
+        }
+    }
+
+    /// Timings of the most recently measured frame.
+    pub fn timings(&self) -> Option<&GpuTimings> {
+        self.timings.as_ref()
+    }
+}
+
+/// Total and per pass times in milliseconds, e.g. `1.50 ms (world 1.00, ui 0.50)`.
+pub fn format_timings(timings: &GpuTimings) -> String {
+    let passes: Vec<String> = timings
+        .passes
+        .iter()
+        .map(|(pass, duration)| format!("{} {:.2}", pass.name(), duration.as_secs_f64() * 1000.0))
+        .collect();
+    format!(
+        "{:.2} ms ({})",
+        timings.total().as_secs_f64() * 1000.0,
+        passes.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_timestamps() {
+        let mut timestamps = [0; QUERY_COUNT as usize];
+        // Shadows: 1000 ticks, world: 500 ticks, sky went backwards
+        timestamps[..6].copy_from_slice(&[100, 1100, 2000, 1900, 3000, 3500]);
+        let passes = [
+            ProfiledPass::Shadows,
+            ProfiledPass::Sky,
+            ProfiledPass::World,
+        ];
+
+        let timings = GpuTimings::from_timestamps(&timestamps, &passes, 2000.0);
+        assert_eq!(
+            timings.passes,
+            [
+                (ProfiledPass::Shadows, Duration::from_millis(2)),
+                (ProfiledPass::World, Duration::from_millis(1)),
+            ]
+        );
+        assert_eq!(timings.total(), Duration::from_millis(3));
+        assert_eq!(
+            format_timings(&timings),
+            "3.00 ms (shadows 2.00, world 1.00)"
+        );
+    }
+}
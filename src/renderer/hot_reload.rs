@@ -0,0 +1,98 @@
+use wgpu::{Device, ErrorFilter, ShaderModule, ShaderModuleDescriptor, ShaderSource};
+
+use crate::renderer::preprocessor::preprocess;
+
+pub use watcher::HotReload;
+
+#[cfg(feature = "hot-reload")]
+mod watcher {
+    use std::{
+        collections::HashSet,
+        path::PathBuf,
+        sync::mpsc::{self, Receiver},
+    };
+
+    use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+    /// Watches shader and texture files for changes, used in debug builds.
+    pub struct HotReload {
+        // Stops watching when dropped
+        _watcher: RecommendedWatcher,
+        events: Receiver<notify::Result<Event>>,
+    }
+
+    impl HotReload {
+        /// Recursively watch all existing paths of `paths`. Only returns `None` in builds without
+        /// the `hot-reload` feature.
+        pub fn new(paths: &[PathBuf]) -> anyhow::Result<Option<Self>> {
+            let (sender, events) = mpsc::channel();
+            let mut watcher = notify::recommended_watcher(sender)?;
+            for path in paths.iter().filter(|path| path.exists()) {
+                watcher.watch(path, RecursiveMode::Recursive)?;
+                log::info!("Watching {} for changes", path.display());
+            }
+
+            Ok(Some(HotReload {
+                _watcher: watcher,
+                events,
+            }))
+        }
+
+        /// Files created or modified since the last call.
+        pub fn changed_files(&self) -> HashSet<PathBuf> {
+            self.events
+                .try_iter()
+                .filter_map(|event| event.ok())
+                .filter(|event| matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)))
+                .flat_map(|event| event.paths)
+                .collect()
+        }
+    }
+}
+
+/// Stand-in without the `hot-reload` feature, so builds without it don't include a file watcher.
+#[cfg(not(feature = "hot-reload"))]
+mod watcher {
+    use std::{collections::HashSet, path::PathBuf};
+
+    pub struct HotReload;
+
+    impl HotReload {
+        /// Hot reloading is disabled, so there is no watcher and nothing to warn about.
+        pub fn new(_paths: &[PathBuf]) -> anyhow::Result<Option<Self>> {
+            Ok(None)
+        }
+
+        pub fn changed_files(&self) -> HashSet<PathBuf> {
+            HashSet::new()
+        }
+    }
+}
+
+/// Preprocess and compile `source` and create render pipelines from it. Validation errors are
+/// logged instead of crashing, in which case `None` is returned so the caller can keep its
+/// previous pipelines.
+pub fn try_create_pipeline<T>(
+    device: &Device,
+    label: &str,
+    source: &str,
+    create_pipeline: impl FnOnce(&ShaderModule) -> T,
+) -> Option<T> {
+    device.push_error_scope(ErrorFilter::Validation);
+    let shader = device.create_shader_module(ShaderModuleDescriptor {
+        label: Some(label),
+        source: ShaderSource::Wgsl(preprocess(source).into()),
+    });
+    let render_pipeline = create_pipeline(&shader);
+
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => {
+            log::error!("Failed to reload {label}: {error}");
+            None
+        }
+        None => {
+            log::info!("Reloaded {label}");
+            Some(render_pipeline)
+        }
+    }
+}
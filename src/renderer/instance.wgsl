@@ -0,0 +1,71 @@
+// Unpacking of cube face instances, shared by all shaders drawing chunk meshes. The constants are
+// injected from Rust by the shader preprocessor.
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coordinates: vec2<f32>,
+};
+
+struct InstanceInput {
+    @location(2) chunk: vec3<i32>,
+    @location(3) packed_bits: u32,
+};
+
+// Position of the face's block or cell inside of the chunk
+fn instance_position(instance: InstanceInput) -> vec3<u32> {
+    return vec3u(
+        instance.packed_bits & POSITION_MASK,
+        (instance.packed_bits >> CHUNK_WIDTH_BITS) & POSITION_MASK,
+        (instance.packed_bits >> (2u * CHUNK_WIDTH_BITS)) & POSITION_MASK
+    );
+}
+
+fn instance_texture_index(instance: InstanceInput) -> u32 {
+    return (instance.packed_bits >> TEXTURE_INDEX_SHIFT) & TEXTURE_INDEX_MASK;
+}
+
+fn instance_direction(instance: InstanceInput) -> u32 {
+    return (instance.packed_bits >> DIRECTION_SHIFT) & DIRECTION_MASK;
+}
+
+// Level of detail: faces of distant chunks cover 2^lod blocks
+fn instance_lod_scale(instance: InstanceInput) -> f32 {
+    return f32(1u << ((instance.packed_bits >> LOD_SHIFT) & LOD_MASK));
+}
+
+// Rotate the cube face model, which points in -Z direction, to point in `direction`
+fn face_model_position(position: vec3<f32>, direction: u32) -> vec3<f32> {
+    switch direction {
+        case 0u: {
+            // -X
+            return vec3f(0, position.xy);
+        }
+        case 1u: {
+            // +X
+            return vec3f(1, position.yx);
+        }
+        case 2u: {
+            // -Y
+            return vec3f(position.y, 0, position.x);
+        }
+        case 3u: {
+            // +Y
+            return vec3f(position.x, 1, position.y);
+        }
+        case 5u: {
+            // +Z
+            return vec3f(position.yx, 1);
+        }
+        default: {
+            // -Z is the default direction of the model
+            return position;
+        }
+    }
+}
+
+// World space position of a vertex of a cube face instance
+fn instance_world_position(model: VertexInput, instance: InstanceInput) -> vec3<f32> {
+    let model_position = face_model_position(model.position, instance_direction(instance));
+    let chunk_origin = f32(CHUNK_DIMENSIONS) * vec3f(instance.chunk);
+    return chunk_origin + instance_lod_scale(instance) * (vec3f(instance_position(instance)) + model_position);
+}
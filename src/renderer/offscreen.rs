@@ -0,0 +1,166 @@
+use std::sync::mpsc;
+
+use anyhow::{bail, Context, Result};
+use image::RgbaImage;
+use wgpu::{
+    Backends, BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Device, DeviceDescriptor,
+    Extent3d, Features, ImageCopyBuffer, ImageCopyTexture, ImageDataLayout, Instance,
+    InstanceDescriptor, Limits, Maintain, MapMode, Origin3d, PowerPreference, Queue,
+    RequestAdapterOptions, Texture, TextureAspect, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView, TextureViewDescriptor, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+use crate::texture;
+
+/// Create a device without a surface to render to. Any adapter works, including software
+/// adapters like llvmpipe, which is the only one used if `force_fallback_adapter` is set.
+pub async fn request_device(force_fallback_adapter: bool) -> Result<(Device, Queue)> {
+    let instance = Instance::new(InstanceDescriptor {
+        backends: Backends::all(),
+        ..Default::default()
+    });
+
+    let adapter = instance
+        .request_adapter(&RequestAdapterOptions {
+            power_preference: PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter,
+        })
+        .await
+        .context("No suitable graphics adapter found")?;
+    log::info!("Using graphics adapter {:?}", adapter.get_info());
+
+    adapter
+        .request_device(
+            &DeviceDescriptor {
+                label: None,
+                required_limits: Limits::default(),
+                required_features: Features::empty(),
+            },
+            None,
+        )
+        .await
+        .context("Failed to create graphics device")
+}
+
+/// Color and depth textures to render a frame into without a window, which can be read back.
+pub struct OffscreenTarget {
+    color_texture: Texture,
+    color_view: TextureView,
+    _depth_texture: Texture,
+    depth_view: TextureView,
+    width: u32,
+    height: u32,
+}
+
+impl OffscreenTarget {
+    pub fn new(device: &Device, color_format: TextureFormat, width: u32, height: u32) -> Self {
+        let color_texture = device.create_texture(&TextureDescriptor {
+            label: Some("offscreen color texture"),
+            size: Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: color_format,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let color_view = color_texture.create_view(&TextureViewDescriptor::default());
+        let (depth_texture, depth_view) = texture::create_depth_texture(device, width, height);
+
+        OffscreenTarget {
+            color_texture,
+            color_view,
+            _depth_texture: depth_texture,
+            depth_view,
+            width,
+            height,
+        }
+    }
+
+    pub fn color_view(&self) -> &TextureView {
+        &self.color_view
+    }
+
+    pub fn depth_view(&self) -> &TextureView {
+        &self.depth_view
+    }
+
+    /// Copy the rendered frame to the CPU, waiting for all submitted rendering to finish.
+    pub fn read_image(&self, device: &Device, queue: &Queue) -> Result<RgbaImage> {
+        let swap_red_and_blue = match self.color_texture.format() {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+            format => bail!("Can't read back textures with format {format:?}"),
+        };
+
+        let unpadded_bytes_per_row = 4 * self.width;
+        // Rows of texture copies have to be aligned
+        let padded_bytes_per_row =
+            unpadded_bytes_per_row.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("offscreen readback buffer"),
+            size: (padded_bytes_per_row * self.height) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("offscreen readback encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                texture: &self.color_texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            self.color_texture.size(),
+        );
+        queue.submit([encoder.finish()]);
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(MapMode::Read, move |result| {
+            // The receiver only goes away if waiting for the device failed
+            let _ = sender.send(result);
+        });
+        device.poll(Maintain::Wait);
+        receiver
+            .recv()
+            .context("Readback buffer was never mapped")?
+            .context("Failed to map readback buffer")?;
+
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * self.height) as usize);
+        for row in slice
+            .get_mapped_range()
+            .chunks_exact(padded_bytes_per_row as usize)
+        {
+            pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]);
+        }
+        buffer.unmap();
+
+        for pixel in pixels.chunks_exact_mut(4) {
+            if swap_red_and_blue {
+                pixel.swap(0, 2);
+            }
+            // Pipelines don't care about the alpha they write, but images are opaque
+            pixel[3] = u8::MAX;
+        }
+
+        RgbaImage::from_raw(self.width, self.height, pixels).context("Readback has the wrong size")
+    }
+}
@@ -0,0 +1,156 @@
+use std::fs;
+
+use crate::{
+    renderer::{
+        shadow_renderer::SHADOW_CASCADE_COUNT,
+        text_renderer::{FONT_ATLAS_COLUMNS, SOLID_GLYPH},
+        DIRECTION_MASK, DIRECTION_SHIFT, LOD_MASK, LOD_SHIFT, POSITION_MASK, TEXTURE_INDEX_MASK,
+        TEXTURE_INDEX_SHIFT,
+    },
+    texture::MAX_TEXTURES,
+    world::{CHUNK_DIMENSIONS, CHUNK_WIDTH_BITS},
+};
+
+/// Shader files that can be included with `#include "<name>"`
+const INCLUDES: &[(&str, &str)] = &[
+    ("camera.wgsl", include_str!("camera.wgsl")),
+    ("environment.wgsl", include_str!("environment.wgsl")),
+    ("instance.wgsl", include_str!("instance.wgsl")),
+    ("fog.wgsl", include_str!("fog.wgsl")),
+];
+const INCLUDE_SOURCE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/renderer");
+
+/// Prepare WGSL source for compilation: prepend the constants shared with Rust and replace
+/// `#include "<name>"` lines with the included file. Includes can include other files, every file
+/// is only included once.
+pub fn preprocess(source: &str) -> String {
+    let mut output = constants();
+    resolve_includes(source, &mut Vec::new(), &mut output);
+    output
+}
+
+fn resolve_includes(source: &str, included: &mut Vec<String>, output: &mut String) {
+    for line in source.lines() {
+        match line
+            .trim()
+            .strip_prefix("#include ")
+            .map(|name| name.trim().trim_matches('"'))
+        {
+            // E.g. fog.wgsl and the shader including it both include environment.wgsl
+            Some(name) if included.iter().any(|include| include == name) => {}
+            Some(name) => match include(name) {
+                Some(contents) => {
+                    included.push(name.to_owned());
+                    resolve_includes(&contents, included, output);
+                }
+                None => log::error!("Unknown shader include {name}"),
+            },
+            None => output.push_str(line),
+        }
+        output.push('\n');
+    }
+}
+
+/// Whether the shader file is only used through `#include`.
+pub fn is_include(name: &str) -> bool {
+    INCLUDES.iter().any(|(include, _)| *include == name)
+}
+
+fn include(name: &str) -> Option<String> {
+    // Prefer the source files in debug builds, so changes can be hot reloaded
+    if cfg!(debug_assertions) {
+        if let Ok(contents) = fs::read_to_string(format!("{INCLUDE_SOURCE_DIR}/{name}")) {
+            return Some(contents);
+        }
+    }
+
+    INCLUDES
+        .iter()
+        .find(|(include, _)| *include == name)
+        .map(|(_, contents)| contents.to_string())
+}
+
+fn constants() -> String {
+    let u32_constants = [
+        ("CHUNK_WIDTH_BITS", CHUNK_WIDTH_BITS),
+        ("POSITION_MASK", POSITION_MASK),
+        ("TEXTURE_INDEX_SHIFT", TEXTURE_INDEX_SHIFT),
+        ("TEXTURE_INDEX_MASK", TEXTURE_INDEX_MASK),
+        ("DIRECTION_SHIFT", DIRECTION_SHIFT),
+        ("DIRECTION_MASK", DIRECTION_MASK),
+        ("LOD_SHIFT", LOD_SHIFT),
+        ("LOD_MASK", LOD_MASK),
+        ("MAX_TEXTURES", MAX_TEXTURES as u32),
+        ("SHADOW_CASCADE_COUNT", SHADOW_CASCADE_COUNT as u32),
+        ("FONT_ATLAS_COLUMNS", FONT_ATLAS_COLUMNS),
+        ("SOLID_GLYPH", SOLID_GLYPH),
+    ];
+
+    let mut constants = format!("const CHUNK_DIMENSIONS: i32 = {CHUNK_DIMENSIONS};\n");
+    for (name, value) in u32_constants {
+        constants.push_str(&format!("const {name}: u32 = {value}u;\n"));
+    }
+    constants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_includes() {
+        let output = preprocess("#include \"instance.wgsl\"\nfn main() {}");
+        assert!(output.contains("fn instance_world_position"));
+        assert!(output.ends_with("fn main() {}\n"));
+        assert!(!output.contains("#include"));
+    }
+
+    #[test]
+    fn shaders_validate() {
+        use wgpu::naga::{
+            front::wgsl,
+            valid::{Capabilities, ValidationFlags, Validator},
+        };
+
+        let shaders = [
+            ("shader.wgsl", include_str!("../shader.wgsl")),
+            ("sky.wgsl", include_str!("sky.wgsl")),
+            ("clouds.wgsl", include_str!("clouds.wgsl")),
+            ("shadow.wgsl", include_str!("shadow.wgsl")),
+            ("reticle.wgsl", include_str!("reticle.wgsl")),
+            ("selection.wgsl", include_str!("selection.wgsl")),
+            ("text.wgsl", include_str!("text.wgsl")),
+        ];
+        for (name, source) in shaders {
+            let module = wgsl::parse_str(&preprocess(source))
+                .unwrap_or_else(|error| panic!("{name} doesn't parse: {error}"));
+            Validator::new(ValidationFlags::all(), Capabilities::default())
+                .validate(&module)
+                .unwrap_or_else(|error| panic!("{name} doesn't validate: {error:?}"));
+        }
+    }
+
+    #[test]
+    fn injects_instance_constants() {
+        let output = preprocess("");
+        for (name, value) in [
+            ("CHUNK_WIDTH_BITS", CHUNK_WIDTH_BITS),
+            ("POSITION_MASK", POSITION_MASK),
+            ("TEXTURE_INDEX_SHIFT", TEXTURE_INDEX_SHIFT),
+            ("TEXTURE_INDEX_MASK", TEXTURE_INDEX_MASK),
+            ("DIRECTION_SHIFT", DIRECTION_SHIFT),
+            ("DIRECTION_MASK", DIRECTION_MASK),
+            ("LOD_SHIFT", LOD_SHIFT),
+            ("LOD_MASK", LOD_MASK),
+        ] {
+            assert!(output.contains(&format!("const {name}: u32 = {value}u;\n")));
+        }
+    }
+
+    #[test]
+    fn includes_files_once() {
+        let output = preprocess("#include \"environment.wgsl\"\n#include \"fog.wgsl\"");
+        assert!(output.contains("fn fog_factor"));
+        assert_eq!(output.matches("struct EnvironmentUniform").count(), 1);
+    }
+}
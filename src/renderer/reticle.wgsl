@@ -1,11 +1,6 @@
-const line_length: f32 = 0.03;
-
-struct CameraUniform {
-    view_proj: mat4x4<f32>,
-};
+#include "camera.wgsl"
 
-@group(0) @binding(0)
-var<uniform> camera: CameraUniform;
+const line_length: f32 = 0.03;
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
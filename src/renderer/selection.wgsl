@@ -0,0 +1,124 @@
+#include "camera.wgsl"
+
+// How far the outline and cracks are pushed out of the block, so they aren't hidden by the block's
+// faces
+const outline_offset: f32 = 0.002;
+// Resolution of the crack pattern, matching the block textures
+const crack_pixels: f32 = 16.0;
+
+struct SelectionUniform {
+    block_position: vec3<f32>,
+    // Fraction of the block that is broken, from 0 to 1
+    break_progress: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> selection: SelectionUniform;
+
+// Draws the 12 edges of the selected block as a line list of 24 vertices
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32
+) -> @builtin(position) vec4<f32> {
+    // Every group of 4 edges runs parallel to one axis, the two lowest bits select the corner of
+    // the edge on the other two axes
+    let edge = vertex_index / 2u;
+    let corner = vec2f(f32(edge & 1u), f32((edge >> 1u) & 1u));
+    let along = f32(vertex_index & 1u);
+
+    var position: vec3<f32>;
+    switch edge / 4u {
+        case 0u: {
+            position = vec3f(along, corner);
+        }
+        case 1u: {
+            position = vec3f(corner.x, along, corner.y);
+        }
+        default: {
+            position = vec3f(corner, along);
+        }
+    }
+
+    return camera.view_proj * vec4f(block_world_position(position), 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4f(0.0, 0.0, 0.0, 0.6);
+}
+
+fn block_world_position(position: vec3<f32>) -> vec3<f32> {
+    let expanded = (position - 0.5) * (1.0 + 2.0 * outline_offset) + 0.5;
+    return selection.block_position + expanded;
+}
+
+struct CrackOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+// Draws the 6 faces of the selected block as a triangle list of 36 vertices
+@vertex
+fn vs_cracks(
+    @builtin(vertex_index) vertex_index: u32
+) -> CrackOutput {
+    // Face index in the order of `Direction`: -X, +X, -Y, +Y, -Z, +Z
+    let face = vertex_index / 6u;
+    var corners = array(vec2f(0, 0), vec2f(1, 0), vec2f(1, 1), vec2f(0, 0), vec2f(1, 1), vec2f(0, 1));
+    let uv = corners[vertex_index % 6u];
+    let side = f32(face & 1u);
+
+    var position: vec3<f32>;
+    switch face / 2u {
+        case 0u: {
+            position = vec3f(side, uv);
+        }
+        case 1u: {
+            position = vec3f(uv.x, side, uv.y);
+        }
+        default: {
+            position = vec3f(uv, side);
+        }
+    }
+
+    var out: CrackOutput;
+    out.clip_position = camera.view_proj * vec4f(block_world_position(position), 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn hash(p: vec2<f32>) -> vec2<f32> {
+    let q = vec2f(dot(p, vec2f(127.1, 311.7)), dot(p, vec2f(269.5, 183.3)));
+    return fract(sin(q) * 43758.5453);
+}
+
+// Cracks follow the borders of a voronoi pattern and spread outwards from the face center while
+// the block is being broken
+@fragment
+fn fs_cracks(in: CrackOutput) -> @location(0) vec4<f32> {
+    let pixel = (floor(in.uv * crack_pixels) + 0.5) / crack_pixels;
+
+    let cell_count = 4.0;
+    let cell = floor(pixel * cell_count);
+    var nearest = 2.0;
+    var second_nearest = 2.0;
+    for (var y = -1.0; y <= 1.0; y += 1.0) {
+        for (var x = -1.0; x <= 1.0; x += 1.0) {
+            let neighbour = cell + vec2f(x, y);
+            let distance = length(neighbour + hash(neighbour) - pixel * cell_count);
+            if distance < nearest {
+                second_nearest = nearest;
+                nearest = distance;
+            } else if distance < second_nearest {
+                second_nearest = distance;
+            }
+        }
+    }
+
+    let on_crack = second_nearest - nearest < 0.3;
+    let reached = length(pixel - 0.5) < selection.break_progress * 0.8 + 0.1 * hash(pixel).x;
+    if selection.break_progress <= 0.0 || !on_crack || !reached {
+        discard;
+    }
+    return vec4f(0.0, 0.0, 0.0, 0.7);
+}
@@ -0,0 +1,249 @@
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, BlendState, Buffer, BufferBindingType, BufferUsages,
+    ColorTargetState, ColorWrites, CompareFunction, DepthBiasState, DepthStencilState, Device,
+    FragmentState, FrontFace, MultisampleState, PipelineLayout, PipelineLayoutDescriptor,
+    PolygonMode, PrimitiveState, PrimitiveTopology, Queue, RenderPass, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource, ShaderStages,
+    StencilState, TextureFormat, VertexState,
+};
+
+use crate::renderer::{hot_reload, preprocessor::preprocess};
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+struct SelectionUniform {
+    /// World position of the selected block
+    block_position: [f32; 3],
+    break_progress: f32,
+}
+
+/// Renders a wireframe outline around the block the camera is looking at, and cracks on it while
+/// it is being broken.
+pub struct SelectionOutline {
+    uniform: Buffer,
+    bind_group: BindGroup,
+    render_pipeline_layout: PipelineLayout,
+    color_format: TextureFormat,
+    outline_pipeline: RenderPipeline,
+    crack_pipeline: RenderPipeline,
+    visible: bool,
+    is_breaking: bool,
+}
+
+impl SelectionOutline {
+    pub fn new(
+        device: &Device,
+        camera_bind_group_layout: &BindGroupLayout,
+        color_format: TextureFormat,
+    ) -> Self {
+        let uniform = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("selection uniform buffer"),
+            contents: bytemuck::cast_slice(&[SelectionUniform::zeroed()]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX | ShaderStages::FRAGMENT,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("selection bind group layout"),
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: uniform.as_entire_binding(),
+            }],
+            label: Some("selection bind group"),
+        });
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("selection shader"),
+            source: ShaderSource::Wgsl(preprocess(include_str!("selection.wgsl")).into()),
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("selection render pipeline layout"),
+            bind_group_layouts: &[camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let (outline_pipeline, crack_pipeline) = SelectionOutline::create_render_pipelines(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            color_format,
+        );
+
+        SelectionOutline {
+            uniform,
+            bind_group,
+            render_pipeline_layout,
+            color_format,
+            outline_pipeline,
+            crack_pipeline,
+            visible: false,
+            is_breaking: false,
+        }
+    }
+
+    /// Create the outline and the crack render pipeline.
+    fn create_render_pipelines(
+        device: &Device,
+        render_pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        color_format: TextureFormat,
+    ) -> (RenderPipeline, RenderPipeline) {
+        let create_pipeline = |label, vertex_entry_point, fragment_entry_point, topology| {
+            SelectionOutline::create_render_pipeline(
+                device,
+                render_pipeline_layout,
+                shader,
+                color_format,
+                label,
+                (vertex_entry_point, fragment_entry_point),
+                topology,
+            )
+        };
+
+        (
+            create_pipeline(
+                "selection render pipeline",
+                "vs_main",
+                "fs_main",
+                PrimitiveTopology::LineList,
+            ),
+            create_pipeline(
+                "crack render pipeline",
+                "vs_cracks",
+                "fs_cracks",
+                PrimitiveTopology::TriangleList,
+            ),
+        )
+    }
+
+    fn create_render_pipeline(
+        device: &Device,
+        render_pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        color_format: TextureFormat,
+        label: &str,
+        (vertex_entry_point, fragment_entry_point): (&str, &str),
+        topology: PrimitiveTopology,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(render_pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: vertex_entry_point,
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: fragment_entry_point,
+                targets: &[Some(ColorTargetState {
+                    format: color_format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: PrimitiveState {
+                topology,
+                strip_index_format: None,
+                front_face: FrontFace::Cw,
+                cull_mode: None,
+                polygon_mode: PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                // Edges and faces on the back of the block stay hidden by its front faces
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::LessEqual,
+                stencil: StencilState::default(),
+                bias: DepthBiasState::default(),
+            }),
+            multisample: MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    /// Rebuild the render pipelines from new shader source, keeping the old ones on errors.
+    pub fn reload_shader(&mut self, device: &Device, source: &str) {
+        if let Some((outline_pipeline, crack_pipeline)) =
+            hot_reload::try_create_pipeline(device, "selection shader", source, |shader| {
+                SelectionOutline::create_render_pipelines(
+                    device,
+                    &self.render_pipeline_layout,
+                    shader,
+                    self.color_format,
+                )
+            })
+        {
+            self.outline_pipeline = outline_pipeline;
+            self.crack_pipeline = crack_pipeline;
+        }
+    }
+
+    /// Outline the block at `block_position`, or nothing if it's `None`. Cracks are drawn while
+    /// `break_progress` is above 0.
+    pub fn update(
+        &mut self,
+        queue: &Queue,
+        block_position: Option<(i32, i32, i32)>,
+        break_progress: f32,
+    ) {
+        self.visible = block_position.is_some();
+        self.is_breaking = break_progress > 0.0;
+        if let Some((x, y, z)) = block_position {
+            queue.write_buffer(
+                &self.uniform,
+                0,
+                bytemuck::cast_slice(&[SelectionUniform {
+                    block_position: [x as f32, y as f32, z as f32],
+                    break_progress,
+                }]),
+            );
+        }
+    }
+
+    pub fn render<'a: 'b, 'b>(
+        &'a self,
+        render_pass: &mut RenderPass<'b>,
+        camera_bind_group: &'b BindGroup,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+
+        if self.is_breaking {
+            render_pass.set_pipeline(&self.crack_pipeline);
+            render_pass.draw(0..36, 0..1);
+        }
+
+        render_pass.set_pipeline(&self.outline_pipeline);
+        render_pass.draw(0..24, 0..1);
+    }
+}
@@ -0,0 +1,17 @@
+#include "instance.wgsl"
+
+struct CascadeUniform {
+    light_view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> cascade: CascadeUniform;
+
+// Same transformation as `vs_main` in shader.wgsl, but into the light space of a shadow cascade
+@vertex
+fn vs_shadow(
+    model: VertexInput,
+    instance: InstanceInput
+) -> @builtin(position) vec4<f32> {
+    return cascade.light_view_proj * vec4f(instance_world_position(model, instance), 1);
+}
@@ -0,0 +1,493 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{vec3, Mat4, Vec3};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
+    BufferBindingType, BufferUsages, CommandEncoder, CompareFunction, DepthBiasState,
+    DepthStencilState, Device, Extent3d, FilterMode, FrontFace, LoadOp, MultisampleState,
+    Operations, PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState,
+    PrimitiveTopology, Queue, RenderPassDepthStencilAttachment, RenderPassDescriptor,
+    RenderPassTimestampWrites, RenderPipeline, RenderPipelineDescriptor, Sampler,
+    SamplerBindingType, SamplerDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderSource,
+    ShaderStages, StencilState, StoreOp, Texture, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor,
+    TextureViewDimension, VertexState,
+};
+
+use crate::{
+    renderer::{hot_reload, preprocessor::preprocess, CubeFaceInstance, Vertex},
+    world::WORLD_HEIGHT,
+};
+
+pub const SHADOW_CASCADE_COUNT: usize = 3;
+/// Shadows are only rendered up to this distance from the camera
+const MAX_SHADOW_DISTANCE: f32 = 192.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShadowQuality {
+    Off,
+    Low,
+    High,
+}
+
+impl ShadowQuality {
+    pub fn next(&self) -> ShadowQuality {
+        match self {
+            ShadowQuality::Off => ShadowQuality::Low,
+            ShadowQuality::Low => ShadowQuality::High,
+            ShadowQuality::High => ShadowQuality::Off,
+        }
+    }
+
+    /// Side length of each cascade's shadow map in texels
+    fn resolution(&self) -> u32 {
+        match self {
+            // Keep a tiny texture bound, the shader doesn't sample it
+            ShadowQuality::Off => 1,
+            ShadowQuality::Low => 1024,
+            ShadowQuality::High => 2048,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+struct CascadeUniform {
+    light_view_proj: [[f32; 4]; 4],
+}
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+struct ShadowUniform {
+    light_view_proj: [[[f32; 4]; 4]; SHADOW_CASCADE_COUNT],
+    /// Radius of each cascade around the camera, w component is unused
+    cascade_radii: [f32; 4],
+    enabled: u32,
+    texel_size: f32,
+    _padding: [u32; 2],
+}
+
+/// Cascaded shadow maps for sunlight. Each cascade covers a sphere around the camera with a
+/// growing radius, so nearby shadows get the highest resolution.
+pub struct Shadows {
+    quality: ShadowQuality,
+    texture: Texture,
+    cascade_views: Vec<TextureView>,
+    sampler: Sampler,
+    cascade_uniforms: Vec<Buffer>,
+    cascade_bind_groups: Vec<BindGroup>,
+    uniform: Buffer,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    render_pipeline_layout: PipelineLayout,
+    render_pipeline: RenderPipeline,
+}
+
+impl Shadows {
+    pub fn new(device: &Device, quality: ShadowQuality) -> Self {
+        let (texture, cascade_views, texture_view) = Shadows::create_texture(device, quality);
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("shadow sampler"),
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            compare: Some(CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let cascade_bind_group_layout =
+            device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+                entries: &[BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("shadow cascade bind group layout"),
+            });
+
+        let cascade_uniforms: Vec<Buffer> = (0..SHADOW_CASCADE_COUNT)
+            .map(|_| {
+                device.create_buffer_init(&BufferInitDescriptor {
+                    label: Some("shadow cascade uniform buffer"),
+                    contents: bytemuck::cast_slice(&[CascadeUniform::zeroed()]),
+                    usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                })
+            })
+            .collect();
+
+        let cascade_bind_groups = cascade_uniforms
+            .iter()
+            .map(|uniform| {
+                device.create_bind_group(&BindGroupDescriptor {
+                    layout: &cascade_bind_group_layout,
+                    entries: &[BindGroupEntry {
+                        binding: 0,
+                        resource: uniform.as_entire_binding(),
+                    }],
+                    label: Some("shadow cascade bind group"),
+                })
+            })
+            .collect();
+
+        let uniform = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("shadow uniform buffer"),
+            contents: bytemuck::cast_slice(&[ShadowUniform::zeroed()]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2Array,
+                        sample_type: TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+            label: Some("shadow bind group layout"),
+        });
+
+        let bind_group = Shadows::create_bind_group(
+            device,
+            &bind_group_layout,
+            &uniform,
+            &texture_view,
+            &sampler,
+        );
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("shadow shader"),
+            source: ShaderSource::Wgsl(preprocess(include_str!("shadow.wgsl")).into()),
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("shadow render pipeline layout"),
+            bind_group_layouts: &[&cascade_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline =
+            Shadows::create_render_pipeline(device, &render_pipeline_layout, &shader);
+
+        Shadows {
+            quality,
+            texture,
+            cascade_views,
+            sampler,
+            cascade_uniforms,
+            cascade_bind_groups,
+            uniform,
+            bind_group_layout,
+            bind_group,
+            render_pipeline_layout,
+            render_pipeline,
+        }
+    }
+
+    fn create_render_pipeline(
+        device: &Device,
+        render_pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("shadow render pipeline"),
+            layout: Some(render_pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: "vs_shadow",
+                buffers: &[Vertex::desc(), CubeFaceInstance::desc()],
+                compilation_options: Default::default(),
+            },
+            // Only depth is written
+            fragment: None,
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                strip_index_format: None,
+                front_face: FrontFace::Cw,
+                // Chunk meshes only contain faces towards air, so both sides have to cast shadows
+                cull_mode: None,
+                polygon_mode: PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: CompareFunction::Less,
+                stencil: StencilState::default(),
+                // Prevent shadow acne on surfaces facing the sun
+                bias: DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    /// Rebuild the render pipeline from new shader source, keeping the old one on errors.
+    pub fn reload_shader(&mut self, device: &Device, source: &str) {
+        if let Some(render_pipeline) =
+            hot_reload::try_create_pipeline(device, "shadow shader", source, |shader| {
+                Shadows::create_render_pipeline(device, &self.render_pipeline_layout, shader)
+            })
+        {
+            self.render_pipeline = render_pipeline;
+        }
+    }
+
+    /// Create the shadow map array texture, one view per cascade to render to and one view of the
+    /// whole array to sample from.
+    fn create_texture(
+        device: &Device,
+        quality: ShadowQuality,
+    ) -> (Texture, Vec<TextureView>, TextureView) {
+        let resolution = quality.resolution();
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("shadow map texture"),
+            size: Extent3d {
+                width: resolution,
+                height: resolution,
+                depth_or_array_layers: SHADOW_CASCADE_COUNT as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Depth32Float,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let cascade_views = (0..SHADOW_CASCADE_COUNT as u32)
+            .map(|cascade| {
+                texture.create_view(&TextureViewDescriptor {
+                    label: Some("shadow cascade view"),
+                    dimension: Some(TextureViewDimension::D2),
+                    base_array_layer: cascade,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        let texture_view = texture.create_view(&TextureViewDescriptor {
+            label: Some("shadow map view"),
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
+        (texture, cascade_views, texture_view)
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        uniform: &Buffer,
+        texture_view: &TextureView,
+        sampler: &Sampler,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            layout: bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(texture_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(sampler),
+                },
+            ],
+            label: Some("shadow bind group"),
+        })
+    }
+
+    pub fn bind_group_layout(&self) -> &BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    pub fn get_quality(&self) -> ShadowQuality {
+        self.quality
+    }
+
+    /// Change the shadow quality, recreating the shadow maps with the matching resolution.
+    pub fn set_quality(&mut self, device: &Device, quality: ShadowQuality) {
+        if quality == self.quality {
+            return;
+        }
+
+        let (texture, cascade_views, texture_view) = Shadows::create_texture(device, quality);
+        self.texture.destroy();
+        self.texture = texture;
+        self.cascade_views = cascade_views;
+        self.bind_group = Shadows::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.uniform,
+            &texture_view,
+            &self.sampler,
+        );
+        self.quality = quality;
+    }
+
+    /// Fit the cascades around the camera and upload the light space matrices.
+    pub fn update(
+        &self,
+        queue: &Queue,
+        camera_position: Vec3,
+        sun_direction: Vec3,
+        view_distance: f32,
+    ) {
+        let shadow_distance = view_distance.min(MAX_SHADOW_DISTANCE);
+        let cascade_radii = [
+            shadow_distance / 9.0,
+            shadow_distance / 3.0,
+            shadow_distance,
+        ];
+        let resolution = self.quality.resolution() as f32;
+
+        // Rotation into light space, the sun is never parallel to the Z axis
+        let light_rotation = Mat4::look_to_lh(Vec3::ZERO, -sun_direction, Vec3::Z);
+
+        let mut light_view_proj = [[[0.0; 4]; 4]; SHADOW_CASCADE_COUNT];
+        for (cascade, radius) in cascade_radii.iter().enumerate() {
+            // Move the cascade in steps of whole texels to avoid shimmering shadow edges
+            let texel_size = 2.0 * radius / resolution;
+            let center = light_rotation.transform_point3(camera_position);
+            let center = vec3(
+                (center.x / texel_size).floor() * texel_size,
+                (center.y / texel_size).floor() * texel_size,
+                center.z,
+            );
+
+            // Include shadow casters between the cascade and the top of the world
+            let projection = Mat4::orthographic_lh(
+                center.x - radius,
+                center.x + radius,
+                center.y - radius,
+                center.y + radius,
+                center.z - radius - WORLD_HEIGHT as f32,
+                center.z + radius,
+            );
+
+            let matrix = projection * light_rotation;
+            light_view_proj[cascade] = matrix.to_cols_array_2d();
+            queue.write_buffer(
+                &self.cascade_uniforms[cascade],
+                0,
+                bytemuck::cast_slice(&[CascadeUniform {
+                    light_view_proj: matrix.to_cols_array_2d(),
+                }]),
+            );
+        }
+
+        let uniform = ShadowUniform {
+            light_view_proj,
+            cascade_radii: [cascade_radii[0], cascade_radii[1], cascade_radii[2], 0.0],
+            enabled: (self.quality != ShadowQuality::Off) as u32,
+            texel_size: 1.0 / resolution,
+            _padding: [0; 2],
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::cast_slice(&[uniform]));
+    }
+
+    /// Render the given cube face instances into each shadow cascade.
+    pub fn render(
+        &self,
+        encoder: &mut CommandEncoder,
+        vertex_buffer: &Buffer,
+        instance_buffer: &Buffer,
+        vertex_count: u32,
+        instance_count: u32,
+        timestamp_writes: Option<RenderPassTimestampWrites>,
+    ) {
+        if self.quality == ShadowQuality::Off {
+            return;
+        }
+
+        let last_cascade = self.cascade_views.len() - 1;
+        for (cascade, (cascade_view, cascade_bind_group)) in self
+            .cascade_views
+            .iter()
+            .zip(&self.cascade_bind_groups)
+            .enumerate()
+        {
+            // Time all cascades together
+            let timestamp_writes =
+                timestamp_writes
+                    .as_ref()
+                    .map(|writes| RenderPassTimestampWrites {
+                        query_set: writes.query_set,
+                        beginning_of_pass_write_index: writes
+                            .beginning_of_pass_write_index
+                            .filter(|_| cascade == 0),
+                        end_of_pass_write_index: writes
+                            .end_of_pass_write_index
+                            .filter(|_| cascade == last_cascade),
+                    });
+
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                label: Some("shadow render pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                    view: cascade_view,
+                    depth_ops: Some(Operations {
+                        load: LoadOp::Clear(1.0),
+                        store: StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes,
+            });
+
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, cascade_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+            render_pass.set_vertex_buffer(1, instance_buffer.slice(..));
+            render_pass.draw(0..vertex_count, 0..instance_count);
+        }
+    }
+}
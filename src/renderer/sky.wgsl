@@ -0,0 +1,103 @@
+#include "camera.wgsl"
+#include "environment.wgsl"
+
+const TAU: f32 = 6.283185307;
+// Half the side length of the sun and moon sprites, measured on a plane at distance 1.0
+const sun_size: f32 = 0.08;
+const moon_size: f32 = 0.06;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Direction from the camera towards this point of the sky
+    @location(0) direction: vec3<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32
+) -> VertexOutput {
+    // Cube around the camera, built from two triangles per face
+    var corners = array<vec3f, 8>(
+        vec3f(-1.0, -1.0, -1.0),
+        vec3f(1.0, -1.0, -1.0),
+        vec3f(-1.0, 1.0, -1.0),
+        vec3f(1.0, 1.0, -1.0),
+        vec3f(-1.0, -1.0, 1.0),
+        vec3f(1.0, -1.0, 1.0),
+        vec3f(-1.0, 1.0, 1.0),
+        vec3f(1.0, 1.0, 1.0),
+    );
+    var indices = array<u32, 36>(
+        0, 2, 1, 1, 2, 3, // -Z
+        4, 5, 6, 5, 7, 6, // +Z
+        0, 4, 2, 2, 4, 6, // -X
+        1, 3, 5, 3, 7, 5, // +X
+        0, 1, 4, 1, 5, 4, // -Y
+        2, 6, 3, 3, 6, 7, // +Y
+    );
+    let direction = corners[indices[vertex_index]];
+
+    // Setting w to 0.0 removes the translation component of the camera, like the reticle does, so
+    // the sky stays infinitely far away
+    let clip_position = camera.view_proj * vec4f(direction, 0.0);
+
+    var out: VertexOutput;
+    // Place the sky on the far plane
+    out.clip_position = clip_position.xyww;
+    out.direction = direction;
+    return out;
+}
+
+fn hash(p: vec3<f32>) -> f32 {
+    return fract(sin(dot(p, vec3f(12.9898, 78.233, 37.719))) * 43758.5453);
+}
+
+// Returns coordinates of `direction` on the plane at distance 1.0 facing `center`, used to draw
+// square sprites around `center`
+fn sprite_coordinates(direction: vec3<f32>, center: vec3<f32>) -> vec2<f32> {
+    let right = normalize(cross(center, vec3f(0.0, 0.0, 1.0)));
+    let up = cross(right, center);
+    let projected = direction / dot(direction, center);
+    return vec2f(dot(projected, right), dot(projected, up));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let direction = normalize(in.direction);
+
+    // Gradient from the fog colored horizon towards the zenith
+    var color = mix(environment.fog_color.rgb, environment.zenith_color.rgb, smoothstep(0.0, 0.6, direction.y));
+
+    // Stars rotate with the sun around the Z axis
+    let angle = -environment.time_of_day * TAU;
+    let star_direction = vec3f(
+        cos(angle) * direction.x - sin(angle) * direction.y,
+        sin(angle) * direction.x + cos(angle) * direction.y,
+        direction.z
+    );
+    let star_cell = floor(star_direction * 200.0);
+    if hash(star_cell) > 0.998 {
+        let brightness = environment.star_visibility * smoothstep(-0.05, 0.1, direction.y) * (0.5 + 0.5 * hash(star_cell + 1.0));
+        color = mix(color, vec3f(1.0), brightness);
+    }
+
+    let sun = environment.sun_direction;
+    if dot(direction, sun) > 0.0 {
+        let sun_coordinates = abs(sprite_coordinates(direction, sun));
+        if max(sun_coordinates.x, sun_coordinates.y) < sun_size {
+            color = vec3f(1.0, 0.95, 0.7);
+        }
+    }
+
+    let moon = -sun;
+    if dot(direction, moon) > 0.0 {
+        let moon_coordinates = sprite_coordinates(direction, moon);
+        if max(abs(moon_coordinates.x), abs(moon_coordinates.y)) < moon_size {
+            // Darker spots as craters
+            let crater = hash(floor(moon_coordinates / moon_size * 4.0).xyy);
+            color = select(vec3f(0.85, 0.87, 0.9), vec3f(0.6, 0.62, 0.68), crater > 0.7);
+        }
+    }
+
+    return vec4f(color, 1.0);
+}
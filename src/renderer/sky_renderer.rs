@@ -0,0 +1,118 @@
+use wgpu::{
+    BindGroup, BindGroupLayout, BlendState, ColorTargetState, ColorWrites, Device, FragmentState,
+    FrontFace, MultisampleState, PipelineLayout, PipelineLayoutDescriptor, PolygonMode,
+    PrimitiveState, PrimitiveTopology, RenderPass, RenderPipeline, RenderPipelineDescriptor,
+    ShaderModule, ShaderModuleDescriptor, ShaderSource, TextureFormat, VertexState,
+};
+
+use crate::renderer::{hot_reload, preprocessor::preprocess};
+
+/// Renders the sky gradient, sun, moon and stars on a cube around the camera.
+pub struct Sky {
+    render_pipeline_layout: PipelineLayout,
+    color_format: TextureFormat,
+    render_pipeline: RenderPipeline,
+}
+
+impl Sky {
+    pub fn new(
+        device: &Device,
+        camera_bind_group_layout: &BindGroupLayout,
+        environment_bind_group_layout: &BindGroupLayout,
+        color_format: TextureFormat,
+    ) -> Self {
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("sky shader"),
+            source: ShaderSource::Wgsl(preprocess(include_str!("sky.wgsl")).into()),
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("sky render pipeline layout"),
+            bind_group_layouts: &[camera_bind_group_layout, environment_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline =
+            Sky::create_render_pipeline(device, &render_pipeline_layout, &shader, color_format);
+
+        Sky {
+            render_pipeline_layout,
+            color_format,
+            render_pipeline,
+        }
+    }
+
+    fn create_render_pipeline(
+        device: &Device,
+        render_pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        color_format: TextureFormat,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("sky render pipeline"),
+            layout: Some(render_pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: color_format,
+                    blend: Some(BlendState::REPLACE),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: FrontFace::Cw,
+                // The camera is inside of the cube
+                cull_mode: None,
+                polygon_mode: PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            // The sky pass doesn't use a depth buffer
+            depth_stencil: None,
+            multisample: MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    /// Rebuild the render pipeline from new shader source, keeping the old one on errors.
+    pub fn reload_shader(&mut self, device: &Device, source: &str) {
+        if let Some(render_pipeline) =
+            hot_reload::try_create_pipeline(device, "sky shader", source, |shader| {
+                Sky::create_render_pipeline(
+                    device,
+                    &self.render_pipeline_layout,
+                    shader,
+                    self.color_format,
+                )
+            })
+        {
+            self.render_pipeline = render_pipeline;
+        }
+    }
+
+    pub fn render<'a: 'b, 'b>(
+        &'a self,
+        render_pass: &mut RenderPass<'b>,
+        camera_bind_group: &'b BindGroup,
+        environment_bind_group: &'b BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, environment_bind_group, &[]);
+        render_pass.draw(0..36, 0..1);
+    }
+}
@@ -0,0 +1,59 @@
+struct TextUniform {
+    // Size of the render target in pixels
+    screen_size: vec2<f32>,
+    // Size of a glyph in the font atlas in texture coordinates
+    glyph_uv_size: vec2<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> text: TextUniform;
+@group(0) @binding(1)
+var font_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var font_sampler: sampler;
+
+struct GlyphInstance {
+    // Top left corner in pixels, from the top left corner of the screen
+    @location(0) position: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    // Index of the glyph in the font atlas, or SOLID_GLYPH for a filled rectangle
+    @location(3) glyph: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coordinates: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) solid: u32,
+};
+
+// Draws one quad per glyph instance as a triangle strip of 4 vertices
+@vertex
+fn vs_main(
+    @builtin(vertex_index) vertex_index: u32,
+    glyph: GlyphInstance,
+) -> VertexOutput {
+    let corner = vec2f(f32(vertex_index & 1u), f32(vertex_index >> 1u));
+    let pixel = glyph.position + corner * glyph.size;
+
+    // Atlas cells are laid out in rows of FONT_ATLAS_COLUMNS glyphs
+    let cell = vec2f(f32(glyph.glyph % FONT_ATLAS_COLUMNS), f32(glyph.glyph / FONT_ATLAS_COLUMNS));
+
+    var out: VertexOutput;
+    out.clip_position = vec4f(pixel / text.screen_size * vec2f(2.0, -2.0) + vec2f(-1.0, 1.0), 0.0, 1.0);
+    out.tex_coordinates = (cell + corner) * text.glyph_uv_size;
+    out.color = glyph.color;
+    out.solid = u32(glyph.glyph == SOLID_GLYPH);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Sample before branching, texture sampling requires uniform control flow
+    let coverage = textureSample(font_texture, font_sampler, in.tex_coordinates).r;
+    if in.solid == 0u && coverage < 0.5 {
+        discard;
+    }
+    return in.color;
+}
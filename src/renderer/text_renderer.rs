@@ -0,0 +1,442 @@
+use std::mem;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, Buffer, BufferAddress,
+    BufferBindingType, BufferDescriptor, BufferUsages, ColorTargetState, ColorWrites,
+    CompareFunction, DepthBiasState, DepthStencilState, Device, Extent3d, FilterMode,
+    FragmentState, FrontFace, ImageDataLayout, MultisampleState, PipelineLayout,
+    PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, Queue, RenderPass,
+    RenderPipeline, RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor, ShaderModule,
+    ShaderModuleDescriptor, ShaderSource, ShaderStages, StencilState, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureViewDescriptor,
+    TextureViewDimension, VertexAttribute, VertexBufferLayout, VertexState, VertexStepMode,
+};
+
+use crate::renderer::{hot_reload, preprocessor::preprocess};
+
+/// Font atlas with the printable ASCII characters from space to `~`, white on black. The glyphs
+/// are from the public domain 6x13 font of the X.Org misc-fixed fonts.
+const FONT_ATLAS: &[u8] = include_bytes!("../../res/font_6x13.png");
+/// Size of a glyph in the font atlas in pixels
+pub const GLYPH_SIZE: (u32, u32) = (6, 13);
+/// Glyphs per row of the font atlas
+pub const FONT_ATLAS_COLUMNS: u32 = 16;
+const FIRST_CHARACTER: char = ' ';
+const LAST_CHARACTER: char = '~';
+/// Shown for characters missing from the font
+const REPLACEMENT_CHARACTER: char = '?';
+/// Glyph index of a filled rectangle instead of a character
+pub const SOLID_GLYPH: u32 = u32::MAX;
+
+#[derive(Debug, Copy, Clone, Pod, Zeroable)]
+#[repr(C)]
+struct TextUniform {
+    screen_size: [f32; 2],
+    glyph_uv_size: [f32; 2],
+}
+
+/// Quad showing a single glyph or a filled rectangle.
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+pub struct GlyphInstance {
+    /// Top left corner in pixels, from the top left corner of the screen
+    position: [f32; 2],
+    size: [f32; 2],
+    color: [f32; 4],
+    glyph: u32,
+}
+
+impl GlyphInstance {
+    fn desc() -> VertexBufferLayout<'static> {
+        const ATTRIBUTES: [VertexAttribute; 4] =
+            wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4, 3 => Uint32];
+        VertexBufferLayout {
+            array_stride: mem::size_of::<GlyphInstance>() as BufferAddress,
+            step_mode: VertexStepMode::Instance,
+            attributes: &ATTRIBUTES,
+        }
+    }
+}
+
+/// Color and size of queued text.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    /// Linear RGBA, alpha blends the text with what's behind it
+    pub color: [f32; 4],
+    /// Integer scales keep the glyphs sharp
+    pub scale: f32,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            color: [1.0; 4],
+            scale: 1.0,
+        }
+    }
+}
+
+/// Index of the glyph of `character` in the font atlas.
+fn glyph_index(character: char) -> u32 {
+    let character = if (FIRST_CHARACTER..=LAST_CHARACTER).contains(&character) {
+        character
+    } else {
+        REPLACEMENT_CHARACTER
+    };
+    character as u32 - FIRST_CHARACTER as u32
+}
+
+/// Width and height of `text` in pixels. Lines are separated by `\n`.
+pub fn measure_text(text: &str, scale: f32) -> (f32, f32) {
+    let (glyph_width, glyph_height) = GLYPH_SIZE;
+    let columns = text
+        .lines()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let rows = text.lines().count();
+    (
+        columns as f32 * glyph_width as f32 * scale,
+        rows as f32 * glyph_height as f32 * scale,
+    )
+}
+
+/// Glyph quads for `text` with its top left corner at `position`. Spaces don't need a quad.
+fn layout_text(text: &str, position: (f32, f32), style: TextStyle) -> Vec<GlyphInstance> {
+    let (glyph_width, glyph_height) = GLYPH_SIZE;
+    let size = [
+        glyph_width as f32 * style.scale,
+        glyph_height as f32 * style.scale,
+    ];
+    let (x, y) = position;
+
+    text.lines()
+        .enumerate()
+        .flat_map(|(row, line)| {
+            line.chars()
+                .enumerate()
+                .filter(|(_, character)| *character != ' ')
+                .map(move |(column, character)| GlyphInstance {
+                    position: [x + column as f32 * size[0], y + row as f32 * size[1]],
+                    size,
+                    color: style.color,
+                    glyph: glyph_index(character),
+                })
+        })
+        .collect()
+}
+
+/// Draws text and rectangles on top of the scene, e.g. for debug overlays and menus. Anything
+/// queued is drawn in a single batch with the next frame and then cleared.
+pub struct TextRenderer {
+    uniform: Buffer,
+    bind_group: BindGroup,
+    instance_buffer: Buffer,
+    buffer_capacity: usize,
+    instance_count: usize,
+    /// Glyphs queued for the next frame
+    glyphs: Vec<GlyphInstance>,
+    render_pipeline_layout: PipelineLayout,
+    color_format: TextureFormat,
+    render_pipeline: RenderPipeline,
+}
+
+impl TextRenderer {
+    /// Renderer for targets of `screen_size` pixels.
+    pub fn new(
+        device: &Device,
+        queue: &Queue,
+        color_format: TextureFormat,
+        screen_size: (u32, u32),
+    ) -> Self {
+        let atlas = image::load_from_memory(FONT_ATLAS)
+            .expect("Embedded font atlas is valid")
+            .to_luma8();
+
+        let uniform = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("text uniform buffer"),
+            contents: bytemuck::cast_slice(&[TextUniform {
+                screen_size: [screen_size.0 as f32, screen_size.1 as f32],
+                glyph_uv_size: [
+                    GLYPH_SIZE.0 as f32 / atlas.width() as f32,
+                    GLYPH_SIZE.1 as f32 / atlas.height() as f32,
+                ],
+            }]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let size = Extent3d {
+            width: atlas.width(),
+            height: atlas.height(),
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&TextureDescriptor {
+            label: Some("font texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::R8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            &atlas,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(atlas.width()),
+                rows_per_image: Some(atlas.height()),
+            },
+            size,
+        );
+        let texture_view = texture.create_view(&TextureViewDescriptor::default());
+
+        // Glyphs are drawn at integer scales, so there is nothing to filter
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            label: Some("font sampler"),
+            mag_filter: FilterMode::Nearest,
+            min_filter: FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::VERTEX,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: TextureViewDimension::D2,
+                        sample_type: TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("text bind group layout"),
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&texture_view),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: BindingResource::Sampler(&sampler),
+                },
+            ],
+            label: Some("text bind group"),
+        });
+
+        let instance_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("glyph instance buffer"),
+            usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            size: 0,
+            mapped_at_creation: false,
+        });
+
+        let shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("text shader"),
+            source: ShaderSource::Wgsl(preprocess(include_str!("text.wgsl")).into()),
+        });
+
+        let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("text render pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = TextRenderer::create_render_pipeline(
+            device,
+            &render_pipeline_layout,
+            &shader,
+            color_format,
+        );
+
+        TextRenderer {
+            uniform,
+            bind_group,
+            instance_buffer,
+            buffer_capacity: 0,
+            instance_count: 0,
+            glyphs: Vec::new(),
+            render_pipeline_layout,
+            color_format,
+            render_pipeline,
+        }
+    }
+
+    fn create_render_pipeline(
+        device: &Device,
+        render_pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        color_format: TextureFormat,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("text render pipeline"),
+            layout: Some(render_pipeline_layout),
+            vertex: VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[GlyphInstance::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(ColorTargetState {
+                    format: color_format,
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                strip_index_format: None,
+                front_face: FrontFace::Cw,
+                cull_mode: None,
+                polygon_mode: PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            // Always on top of the scene
+            depth_stencil: Some(DepthStencilState {
+                format: TextureFormat::Depth32Float,
+                depth_write_enabled: false,
+                depth_compare: CompareFunction::Always,
+                stencil: StencilState::default(),
+                bias: DepthBiasState::default(),
+            }),
+            multisample: MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        })
+    }
+
+    /// Rebuild the render pipeline from new shader source, keeping the old one on errors.
+    pub fn reload_shader(&mut self, device: &Device, source: &str) {
+        if let Some(render_pipeline) =
+            hot_reload::try_create_pipeline(device, "text shader", source, |shader| {
+                TextRenderer::create_render_pipeline(
+                    device,
+                    &self.render_pipeline_layout,
+                    shader,
+                    self.color_format,
+                )
+            })
+        {
+            self.render_pipeline = render_pipeline;
+        }
+    }
+
+    /// Draw `text` with its top left corner at `position` in pixels with the next frame. Lines are
+    /// separated by `\n`.
+    pub fn queue_text(&mut self, text: &str, position: (f32, f32), style: TextStyle) {
+        self.glyphs.extend(layout_text(text, position, style));
+    }
+
+    /// Draw a filled rectangle with the next frame, e.g. as background of text queued after it.
+    pub fn queue_rectangle(&mut self, position: (f32, f32), size: (f32, f32), color: [f32; 4]) {
+        self.glyphs.push(GlyphInstance {
+            position: [position.0, position.1],
+            size: [size.0, size.1],
+            color,
+            glyph: SOLID_GLYPH,
+        });
+    }
+
+    /// Change the size of the render target in pixels, which text positions are relative to.
+    pub fn set_screen_size(&self, queue: &Queue, screen_size: (u32, u32)) {
+        queue.write_buffer(
+            &self.uniform,
+            0,
+            bytemuck::cast_slice(&[screen_size.0 as f32, screen_size.1 as f32]),
+        );
+    }
+
+    /// Upload the queued glyphs for the next frame and clear the queue.
+    pub fn update(&mut self, device: &Device, queue: &Queue) {
+        self.instance_count = self.glyphs.len();
+        if self.glyphs.len() > self.buffer_capacity {
+            self.instance_buffer.destroy();
+            self.instance_buffer = device.create_buffer_init(&BufferInitDescriptor {
+                label: Some("glyph instance buffer"),
+                contents: bytemuck::cast_slice(&self.glyphs),
+                usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+            });
+            self.buffer_capacity = self.glyphs.len();
+        } else if !self.glyphs.is_empty() {
+            queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&self.glyphs));
+        }
+        self.glyphs.clear();
+    }
+
+    pub fn render<'a: 'b, 'b>(&'a self, render_pass: &mut RenderPass<'b>) {
+        if self.instance_count == 0 {
+            return;
+        }
+
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..4, 0..self.instance_count as u32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_characters_to_glyphs() {
+        assert_eq!(glyph_index(' '), 0);
+        assert_eq!(glyph_index('A'), 33);
+        assert_eq!(glyph_index('~'), 94);
+        assert_eq!(glyph_index('ä'), glyph_index('?'));
+        assert_eq!(glyph_index('\t'), glyph_index('?'));
+    }
+
+    #[test]
+    fn lays_out_lines() {
+        let style = TextStyle {
+            color: [1.0, 0.0, 0.0, 1.0],
+            scale: 2.0,
+        };
+        let glyphs = layout_text("a b\nc", (10.0, 20.0), style);
+
+        let positions: Vec<_> = glyphs.iter().map(|glyph| glyph.position).collect();
+        assert_eq!(positions, [[10.0, 20.0], [34.0, 20.0], [10.0, 46.0]]);
+        assert!(glyphs.iter().all(|glyph| glyph.size == [12.0, 26.0]));
+        assert_eq!(measure_text("a b\nc", 2.0), (36.0, 52.0));
+    }
+}
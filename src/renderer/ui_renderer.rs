@@ -1,12 +1,16 @@
 use wgpu::{
     BindGroup, BindGroupLayout, BlendState, ColorTargetState, ColorWrites, CompareFunction,
     DepthBiasState, DepthStencilState, Device, FragmentState, FrontFace, MultisampleState,
-    PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology, RenderPass,
-    RenderPipeline, RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, StencilState,
-    TextureFormat, VertexState,
+    PipelineLayout, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, PrimitiveTopology,
+    RenderPass, RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor,
+    ShaderSource, StencilState, TextureFormat, VertexState,
 };
 
+use crate::renderer::{hot_reload, preprocessor::preprocess};
+
 pub struct Reticle {
+    render_pipeline_layout: PipelineLayout,
+    color_format: TextureFormat,
     render_pipeline: RenderPipeline,
 }
 
@@ -18,7 +22,7 @@ impl Reticle {
     ) -> Self {
         let shader = device.create_shader_module(ShaderModuleDescriptor {
             label: Some("reticle shader"),
-            source: ShaderSource::Wgsl(include_str!("reticle.wgsl").into()),
+            source: ShaderSource::Wgsl(preprocess(include_str!("reticle.wgsl")).into()),
         });
 
         let render_pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
@@ -27,17 +31,33 @@ impl Reticle {
             push_constant_ranges: &[],
         });
 
-        let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+        let render_pipeline =
+            Reticle::create_render_pipeline(device, &render_pipeline_layout, &shader, color_format);
+
+        Reticle {
+            render_pipeline_layout,
+            color_format,
+            render_pipeline,
+        }
+    }
+
+    fn create_render_pipeline(
+        device: &Device,
+        render_pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        color_format: TextureFormat,
+    ) -> RenderPipeline {
+        device.create_render_pipeline(&RenderPipelineDescriptor {
             label: Some("reticle render pipeline"),
-            layout: Some(&render_pipeline_layout),
+            layout: Some(render_pipeline_layout),
             vertex: VertexState {
-                module: &shader,
+                module: shader,
                 entry_point: "vs_main",
                 buffers: &[],
                 compilation_options: Default::default(),
             },
             fragment: Some(FragmentState {
-                module: &shader,
+                module: shader,
                 entry_point: "fs_main",
                 targets: &[Some(ColorTargetState {
                     format: color_format,
@@ -68,9 +88,23 @@ impl Reticle {
                 alpha_to_coverage_enabled: false,
             },
             multiview: None,
-        });
+        })
+    }
 
-        Reticle { render_pipeline }
+    /// Rebuild the render pipeline from new shader source, keeping the old one on errors.
+    pub fn reload_shader(&mut self, device: &Device, source: &str) {
+        if let Some(render_pipeline) =
+            hot_reload::try_create_pipeline(device, "reticle shader", source, |shader| {
+                Reticle::create_render_pipeline(
+                    device,
+                    &self.render_pipeline_layout,
+                    shader,
+                    self.color_format,
+                )
+            })
+        {
+            self.render_pipeline = render_pipeline;
+        }
     }
 
     pub fn render<'a: 'b, 'b>(
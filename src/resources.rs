@@ -0,0 +1,370 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use zip::ZipArchive;
+
+/// Directory containing the user's resource packs
+pub const RESOURCE_PACK_DIR: &str = "resourcepacks";
+const DEFAULT_PACK_SOURCE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/res/default");
+const BLOCK_DEFINITIONS_FILE: &str = "blocks.toml";
+
+/// Files of the default resource pack, which is always loaded with the lowest priority
+const DEFAULT_PACK: &[(&str, &[u8])] = &[
+    ("blocks.toml", include_bytes!("../res/default/blocks.toml")),
+    (
+        "textures/stone.png",
+        include_bytes!("../res/default/textures/stone.png"),
+    ),
+    (
+        "textures/grass-top.png",
+        include_bytes!("../res/default/textures/grass-top.png"),
+    ),
+    (
+        "textures/dirt.png",
+        include_bytes!("../res/default/textures/dirt.png"),
+    ),
+    (
+        "textures/sand.png",
+        include_bytes!("../res/default/textures/sand.png"),
+    ),
+    (
+        "textures/gravel.png",
+        include_bytes!("../res/default/textures/gravel.png"),
+    ),
+    (
+        "textures/andesite.png",
+        include_bytes!("../res/default/textures/andesite.png"),
+    ),
+    (
+        "textures/snow.png",
+        include_bytes!("../res/default/textures/snow.png"),
+    ),
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockDefinition {
+    /// Texture name, resolving to `textures/<name>.png`
+    pub texture: String,
+    /// Seconds needed to break the block
+    #[serde(default = "default_hardness")]
+    pub hardness: f32,
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+enum PackSource {
+    Embedded {
+        /// Read the files from the source directory if possible, so they can be hot reloaded
+        prefer_source_files: bool,
+    },
+    Directory(PathBuf),
+    /// Zip archives are small, so their files are kept in memory
+    Zip(HashMap<String, Vec<u8>>),
+}
+
+struct ResourcePack {
+    name: String,
+    source: PackSource,
+}
+
+impl ResourcePack {
+    fn open(path: &Path) -> Result<Self> {
+        let name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+
+        if path.is_dir() {
+            return Ok(ResourcePack {
+                name,
+                source: PackSource::Directory(path.to_owned()),
+            });
+        }
+
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let mut files = HashMap::new();
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index)?;
+            if !file.is_file() {
+                continue;
+            }
+            let mut contents = Vec::with_capacity(file.size() as usize);
+            file.read_to_end(&mut contents)?;
+            files.insert(file.name().replace('\\', "/"), contents);
+        }
+
+        Ok(ResourcePack {
+            name,
+            source: PackSource::Zip(strip_top_level_folder(files)),
+        })
+    }
+
+    /// Read a file of this pack, `None` if the pack doesn't contain it.
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        match &self.source {
+            PackSource::Embedded {
+                prefer_source_files,
+            } => {
+                if *prefer_source_files {
+                    if let Ok(contents) = fs::read(Path::new(DEFAULT_PACK_SOURCE_DIR).join(path)) {
+                        return Ok(Some(contents));
+                    }
+                }
+                Ok(DEFAULT_PACK
+                    .iter()
+                    .find(|(name, _)| *name == path)
+                    .map(|(_, contents)| contents.to_vec()))
+            }
+            PackSource::Directory(directory) => match fs::read(directory.join(path)) {
+                Ok(contents) => Ok(Some(contents)),
+                Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+                Err(error) => Err(error.into()),
+            },
+            PackSource::Zip(files) => Ok(files.get(path).cloned()),
+        }
+    }
+}
+
+/// Zipping a pack's folder puts all of its files under a single top-level folder, which is removed
+/// from their paths. Packs with block definitions or textures at their root are kept as they are,
+/// even if all of their files are in `textures/`.
+fn strip_top_level_folder(files: HashMap<String, Vec<u8>>) -> HashMap<String, Vec<u8>> {
+    if files
+        .keys()
+        .any(|name| name == BLOCK_DEFINITIONS_FILE || name.starts_with("textures/"))
+    {
+        return files;
+    }
+
+    let top_level_folder = |name: &str| name.split_once('/').map(|(folder, _)| folder.to_owned());
+    let Some(folder) = files.keys().next().and_then(|name| top_level_folder(name)) else {
+        return files;
+    };
+    if !files
+        .keys()
+        .all(|name| top_level_folder(name).as_ref() == Some(&folder))
+    {
+        return files;
+    }
+
+    files
+        .into_iter()
+        .map(|(name, contents)| (name[folder.len() + 1..].to_owned(), contents))
+        .collect()
+}
+
+/// Stack of resource packs. Files of packs with a higher priority replace the ones of packs with
+/// a lower priority.
+pub struct ResourcePacks {
+    /// `None` if only the embedded default pack is used
+    directory: Option<PathBuf>,
+    /// Ordered from lowest to highest priority
+    packs: Vec<ResourcePack>,
+}
+
+impl ResourcePacks {
+    /// Load the embedded default pack and all packs in `directory`, which may be directories or
+    /// `.zip` files. User packs are ordered by file name, later names take priority. Other files
+    /// are ignored, and packs that fail to open are skipped.
+    pub fn load(directory: &Path) -> Result<Self> {
+        let mut packs = vec![ResourcePack {
+            name: "default".to_owned(),
+            // Prefer the source files in debug builds, so changes can be hot reloaded
+            source: PackSource::Embedded {
+                prefer_source_files: cfg!(debug_assertions),
+            },
+        }];
+
+        if directory.is_dir() {
+            let mut paths = fs::read_dir(directory)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<std::io::Result<Vec<PathBuf>>>()?;
+            paths.sort();
+
+            for path in paths {
+                let is_zip = path
+                    .extension()
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+                if !path.is_dir() && !is_zip {
+                    log::debug!("Ignoring {} in {}", path.display(), directory.display());
+                    continue;
+                }
+                match ResourcePack::open(&path) {
+                    Ok(pack) => packs.push(pack),
+                    Err(error) => {
+                        log::error!("Skipping resource pack {}: {error:#}", path.display())
+                    }
+                }
+            }
+        }
+
+        log::info!(
+            "Loaded resource packs: {}",
+            packs
+                .iter()
+                .map(|pack| pack.name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", ")
+        );
+
+        Ok(ResourcePacks {
+            directory: Some(directory.to_owned()),
+            packs,
+        })
+    }
+
+    /// Only the embedded default pack, as compiled into the binary. Renders with it don't depend
+    /// on the working directory or on changed source files, e.g. for golden image tests.
+    #[cfg(test)]
+    pub fn embedded() -> Self {
+        ResourcePacks {
+            directory: None,
+            packs: vec![ResourcePack {
+                name: "default".to_owned(),
+                source: PackSource::Embedded {
+                    prefer_source_files: false,
+                },
+            }],
+        }
+    }
+
+    /// Directory the user packs are loaded from, `None` for the embedded default pack alone.
+    pub fn directory(&self) -> Option<&Path> {
+        self.directory.as_deref()
+    }
+
+    /// Read a file from the pack with the highest priority containing it.
+    pub fn read(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        for pack in self.packs.iter().rev() {
+            if let Some(contents) = pack
+                .read(path)
+                .with_context(|| format!("Failed to read {path} from {}", pack.name))?
+            {
+                return Ok(Some(contents));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Block definitions by block name, merged from all packs.
+    pub fn block_definitions(&self) -> Result<HashMap<String, BlockDefinition>> {
+        let mut definitions = HashMap::new();
+        for pack in &self.packs {
+            if let Some(contents) = pack.read(BLOCK_DEFINITIONS_FILE)? {
+                let pack_definitions: HashMap<String, BlockDefinition> =
+                    toml::from_str(std::str::from_utf8(&contents)?).with_context(|| {
+                        format!("Invalid {BLOCK_DEFINITIONS_FILE} in {}", pack.name)
+                    })?;
+                definitions.extend(pack_definitions);
+            }
+        }
+        Ok(definitions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    use super::*;
+
+    fn write_zip(path: &Path, files: &[(&str, &[u8])]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, contents) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn later_packs_take_priority() {
+        let directory =
+            std::env::temp_dir().join(format!("resource-pack-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(directory.join("a/textures")).unwrap();
+        fs::write(directory.join("a/textures/stone.png"), "a").unwrap();
+        fs::write(directory.join("a/textures/sand.png"), "a").unwrap();
+        fs::write(
+            directory.join("a/blocks.toml"),
+            "stone = { texture = \"sand\" }",
+        )
+        .unwrap();
+        // Zipped folder, with its files under a top-level folder
+        write_zip(
+            &directory.join("b.zip"),
+            &[("b/textures/stone.png", b"b"), ("b/blocks.toml", b"")],
+        );
+        // Not packs, ignored or skipped without failing the load
+        fs::write(directory.join("c.txt"), "not a pack").unwrap();
+        fs::write(directory.join("d.zip"), "not a zip").unwrap();
+
+        let packs = ResourcePacks::load(&directory).unwrap();
+
+        assert_eq!(
+            packs
+                .packs
+                .iter()
+                .map(|pack| pack.name.as_str())
+                .collect::<Vec<_>>(),
+            ["default", "a", "b.zip"]
+        );
+        assert_eq!(packs.read("textures/stone.png").unwrap().unwrap(), b"b");
+        assert_eq!(packs.read("textures/sand.png").unwrap().unwrap(), b"a");
+        assert!(packs.read("textures/dirt.png").unwrap().is_some());
+        assert_eq!(packs.read("textures/missing.png").unwrap(), None);
+
+        let definitions = packs.block_definitions().unwrap();
+        assert_eq!(definitions["stone"].texture, "sand");
+        assert_eq!(definitions["dirt"].texture, "dirt");
+        let _ = fs::remove_dir_all(&directory);
+    }
+
+    #[test]
+    fn reads_zip_packs() {
+        let path =
+            std::env::temp_dir().join(format!("resource-pack-test-{}.zip", std::process::id()));
+        write_zip(
+            &path,
+            &[
+                ("blocks.toml", b"stone = { texture = \"stone\" }"),
+                ("textures/stone.png", b"stone"),
+            ],
+        );
+        let pack = ResourcePack::open(&path);
+        let _ = fs::remove_file(&path);
+        let pack = pack.unwrap();
+
+        // Files at the root are kept as they are
+        assert_eq!(pack.read("textures/stone.png").unwrap().unwrap(), b"stone");
+        assert!(pack.read(BLOCK_DEFINITIONS_FILE).unwrap().is_some());
+        assert_eq!(pack.read("textures/dirt.png").unwrap(), None);
+    }
+
+    #[test]
+    fn keeps_texture_only_zip_packs() {
+        let path = std::env::temp_dir().join(format!(
+            "resource-pack-test-textures-{}.zip",
+            std::process::id()
+        ));
+        write_zip(&path, &[("textures/stone.png", b"stone")]);
+        let pack = ResourcePack::open(&path);
+        let _ = fs::remove_file(&path);
+        let pack = pack.unwrap();
+
+        // All files share the `textures` folder, which isn't a top-level folder to remove
+        assert_eq!(pack.read("textures/stone.png").unwrap().unwrap(), b"stone");
+        assert_eq!(pack.read("stone.png").unwrap(), None);
+    }
+}
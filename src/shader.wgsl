@@ -1,25 +1,32 @@
-struct CameraUniform {
-    view_proj: mat4x4<f32>,
-};
-
-@group(1) @binding(0)
-var<uniform> camera: CameraUniform;
+#include "camera.wgsl"
+#include "environment.wgsl"
+#include "instance.wgsl"
+#include "fog.wgsl"
 
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) tex_coordinates: vec2<f32>,
+struct ShadowUniform {
+    light_view_proj: array<mat4x4<f32>, SHADOW_CASCADE_COUNT>,
+    // Radius of each cascade around the camera
+    cascade_radii: vec4<f32>,
+    enabled: u32,
+    // Size of a shadow map texel in texture coordinates
+    texel_size: f32,
 };
 
-struct InstanceInput {
-    @location(2) chunk: vec3<i32>,
-    @location(3) packed_bits: u32,
-};
+fn face_normal(direction: u32) -> vec3<f32> {
+    // Even directions point towards the negative axis
+    var normal = vec3f(0.0);
+    normal[direction >> 1u] = select(-1.0, 1.0, (direction & 1u) == 1u);
+    return normal;
+}
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coordinates: vec2<f32>,
     @location(1) @interpolate(flat) tex_index: u32,
     @location(2) @interpolate(flat) direction: u32,
+    // Position relative to the camera
+    @location(3) camera_offset: vec3<f32>,
+    @location(4) world_position: vec3<f32>,
 };
 
 @vertex
@@ -27,74 +34,114 @@ fn vs_main(
     model: VertexInput,
     instance: InstanceInput
 ) -> VertexOutput {
-    let chunk_relative_coords = vec3u(
-        (instance.packed_bits >>  0) & 0x1F,
-        (instance.packed_bits >>  5) & 0x1F,
-        (instance.packed_bits >> 10) & 0x1F
-    );
+    let direction = instance_direction(instance);
+    let lod_scale = instance_lod_scale(instance);
 
-    let tex_index = (instance.packed_bits >> 15) & 0xFF;
-    let direction = (instance.packed_bits >> 23) & 0x7;
-
-    var model_coords = model.position;
+    // The face models of -X and +Z are mirrored, swap the texture axes to keep textures upright
     var model_tex_coordinates = model.tex_coordinates;
-    
-    switch direction {
-        case 0u: {
-            // -X
-            model_coords = vec3f(0, model_coords.xy);
-            model_tex_coordinates = model_tex_coordinates.yx;
-        }
-        case 1u: {
-            // +X
-            model_coords = vec3f(1, model_coords.yx);
-        }
-        case 2u: {
-            // -Y
-            model_coords = vec3f(model_coords.y, 0, model_coords.x);
-        }
-        case 3u: {
-            // +Y
-            model_coords = vec3f(model_coords.x, 1, model_coords.y);
-        }
-        case 4u, default {
-            // case 4u is case -Z, which is the default direction of the model
-        }
-        case 5u: {
-            // +Z
-            model_coords = vec3f(model_coords.yx, 1);
-            model_tex_coordinates = model_tex_coordinates.yx;
-        }
+    if direction == 0u || direction == 5u {
+        model_tex_coordinates = model_tex_coordinates.yx;
     }
-    
-    let global_position = 32 * vec3f(instance.chunk) + vec3f(chunk_relative_coords) + model_coords;
+
+    let global_position = instance_world_position(model, instance);
 
     var out: VertexOutput;
     out.clip_position = camera.view_proj * vec4f(global_position, 1);
-    out.tex_coordinates = model_tex_coordinates;
-    out.tex_index = tex_index;
+    // Repeat the texture once per block
+    out.tex_coordinates = lod_scale * model_tex_coordinates;
+    out.tex_index = instance_texture_index(instance);
     out.direction = direction;
+    out.camera_offset = global_position - camera.position.xyz;
+    out.world_position = global_position;
     return out;
 }
 
-@group(0) @binding(0)
-var t_diffuse: binding_array<texture_2d<f32>>;
-@group(0) @binding(1)
+@group(2) @binding(0)
+var t_diffuse: texture_2d_array<f32>;
+@group(2) @binding(1)
 var s_diffuse: sampler;
 
+// Frames of a texture in `t_diffuse`, indexed by texture index
+struct TextureAnimation {
+    first_layer: u32,
+    frame_count: u32,
+    // Seconds each frame is shown
+    frame_time: f32,
+    interpolate: u32,
+};
+
+@group(2) @binding(2)
+var<uniform> animations: array<TextureAnimation, MAX_TEXTURES>;
+
+@group(3) @binding(0)
+var<uniform> shadow: ShadowUniform;
+@group(3) @binding(1)
+var t_shadow: texture_depth_2d_array;
+@group(3) @binding(2)
+var s_shadow: sampler_comparison;
+
+// Returns how much sunlight reaches a fragment, between 0.0 (fully shadowed) and 1.0 (fully lit)
+fn shadow_factor(world_position: vec3<f32>, normal: vec3<f32>, camera_offset: vec3<f32>) -> f32 {
+    if shadow.enabled == 0u {
+        return 1.0;
+    }
+
+    // Use the smallest cascade containing the fragment
+    let distance = length(camera_offset);
+    var cascade = 0u;
+    while cascade < SHADOW_CASCADE_COUNT && distance > shadow.cascade_radii[cascade] {
+        cascade += 1u;
+    }
+    if cascade == SHADOW_CASCADE_COUNT {
+        return 1.0;
+    }
+
+    // Offset along the normal by about one shadow map texel to prevent shadow acne
+    let texel_world_size = 2.0 * shadow.cascade_radii[cascade] * shadow.texel_size;
+    let position = world_position + 1.5 * texel_world_size * normal;
+    let light_position = shadow.light_view_proj[cascade] * vec4f(position, 1.0);
+    let coordinates = light_position.xy * vec2f(0.5, -0.5) + 0.5;
+    if any(coordinates < vec2f(0.0)) || any(coordinates > vec2f(1.0)) || light_position.z > 1.0 {
+        return 1.0;
+    }
+
+    // 3x3 percentage closer filtering for soft shadow edges
+    var lit = 0.0;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let offset = vec2f(f32(x), f32(y)) * shadow.texel_size;
+            lit += textureSampleCompareLevel(t_shadow, s_shadow, coordinates + offset, cascade, light_position.z);
+        }
+    }
+    return lit / 9.0;
+}
+
+// Sample the current frame of a possibly animated texture
+fn sample_texture(tex_index: u32, tex_coordinates: vec2<f32>) -> vec4<f32> {
+    let animation = animations[tex_index];
+    let frame_position = environment.time / animation.frame_time;
+    let frame = u32(frame_position) % animation.frame_count;
+    let color = textureSample(t_diffuse, s_diffuse, tex_coordinates, animation.first_layer + frame);
+
+    // Always sample the next frame, texture sampling requires uniform control flow
+    let next_frame = (frame + 1u) % animation.frame_count;
+    let next_color = textureSample(t_diffuse, s_diffuse, tex_coordinates, animation.first_layer + next_frame);
+    let blend = select(0.0, fract(frame_position), animation.interpolate != 0u);
+    return mix(color, next_color, blend);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var lighting_factor: f32;
-    if in.direction < 2 {
-        // X or -X
-        lighting_factor = 0.7;
-    } else if in.direction >= 4 {
-        // Z or -Z
-        lighting_factor = 0.85;
-    } else {
-        // Y or -Y
-        lighting_factor = 1.1;
+    let normal = face_normal(in.direction);
+    let diffuse = max(dot(normal, environment.sun_direction), 0.0);
+    // Faces facing away from the sun can't receive direct sunlight anyway
+    var shadow = 0.0;
+    if diffuse > 0.0 {
+        shadow = shadow_factor(in.world_position, normal, in.camera_offset);
     }
+    // Faces facing away from the sun and shadowed faces are still lit by the sky
+    let lighting_factor = environment.ambient_light + environment.sun_intensity * (0.4 + 0.6 * diffuse * shadow);
 
-    return lighting_factor * textureSample(t_diffuse[in.tex_index], s_diffuse, in.tex_coordinates);
+    let color = lighting_factor * sample_texture(in.tex_index, in.tex_coordinates);
+    return vec4f(mix(color.rgb, environment.fog_color.rgb, fog_factor(in.camera_offset)), color.a);
 }
@@ -1,109 +1,379 @@
-use std::{fs, num::NonZeroU32};
-
 use anyhow::*;
-use image::GenericImageView;
-use wgpu::{BindGroup, BindGroupLayout, TextureUsages, TextureView};
-
-const TEXTURES: [&str; 7] = [
-    "res/stone.png",
-    "res/grass-top.png",
-    "res/dirt.png",
-    "res/sand.png",
-    "res/gravel.png",
-    "res/andesite.png",
-    "res/snow.png",
-];
-
-/// Create bind group and bind group layout for a texture array and a texture sampler.
-pub fn load_textures(
-    device: &wgpu::Device,
-    queue: &wgpu::Queue,
-) -> Result<(BindGroupLayout, BindGroup)> {
-    let texture_bind_group_layout =
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: NonZeroU32::new(TEXTURES.len() as u32),
+use bytemuck::{Pod, Zeroable};
+use image::{imageops::FilterType, Rgba, RgbaImage};
+use serde::Deserialize;
+use wgpu::{util::DeviceExt, BindGroup, BindGroupLayout, TextureUsages};
+
+use crate::{resources::ResourcePacks, world::blocks::Block};
+
+/// All textures are scaled up to at least this size, so linear magnification keeps low resolution
+/// textures sharp.
+const MIN_TEXTURE_SIZE: u32 = 128;
+const MAX_ANISOTROPY: u16 = 16;
+/// Size of the squares of the texture used for missing textures
+const CHECKERBOARD_CELL_SIZE: u32 = 8;
+/// Texture indices are stored in 8 bits of each cube face instance
+pub const MAX_TEXTURES: usize = 256;
+
+/// Animation metadata of a texture, read from `textures/<name>.toml` next to the texture. The
+/// texture is a vertical strip of square frames.
+#[derive(Debug, Deserialize)]
+struct AnimationMetadata {
+    /// Seconds each frame is shown
+    frame_time: f32,
+    /// Blend smoothly between consecutive frames
+    #[serde(default)]
+    interpolate: bool,
+}
+
+/// Frames of a texture in the texture array, static textures have a single frame.
+#[derive(Debug, Copy, Clone, PartialEq, Pod, Zeroable)]
+#[repr(C)]
+struct TextureAnimation {
+    first_layer: u32,
+    frame_count: u32,
+    frame_time: f32,
+    interpolate: u32,
+}
+
+impl TextureAnimation {
+    /// Animation of the vertical strip of frames `img` described by `metadata`, with its frames
+    /// stored from `first_layer` on.
+    fn parse(metadata: &str, img: &RgbaImage, first_layer: u32) -> Result<Self> {
+        let metadata: AnimationMetadata = toml::from_str(metadata)?;
+        ensure!(
+            metadata.frame_time.is_finite() && metadata.frame_time > 0.0,
+            "Frame time has to be positive, not {}",
+            metadata.frame_time
+        );
+
+        Ok(TextureAnimation {
+            first_layer,
+            frame_count: (img.height() / img.width()).max(1),
+            frame_time: metadata.frame_time,
+            interpolate: metadata.interpolate as u32,
+        })
+    }
+
+    /// Single frame at `first_layer`.
+    fn still(first_layer: u32) -> Self {
+        TextureAnimation {
+            first_layer,
+            frame_count: 1,
+            frame_time: 1.0,
+            interpolate: 0,
+        }
+    }
+}
+
+/// Bind group layout for a texture array, a texture sampler and the animation table.
+pub fn create_bind_group_layout(device: &wgpu::Device) -> BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
                 },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
                 },
-            ],
-            label: Some("texture bind gorup layout"),
-        });
+                count: None,
+            },
+        ],
+        label: Some("texture bind group layout"),
+    })
+}
 
+/// Load the block textures of the resource packs and create a bind group with the layout of
+/// [`create_bind_group_layout`]. Entry `i` of the animation table locates the frames of the block
+/// with texture index `i` in the array.
+pub fn load_textures(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture_bind_group_layout: &BindGroupLayout,
+    resource_packs: &ResourcePacks,
+) -> Result<BindGroup> {
+    // Anisotropic filtering requires linear filtering for all filter modes
     let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
         address_mode_u: wgpu::AddressMode::Repeat,
         address_mode_v: wgpu::AddressMode::Repeat,
         address_mode_w: wgpu::AddressMode::Repeat,
-        mag_filter: wgpu::FilterMode::Nearest,
-        min_filter: wgpu::FilterMode::Nearest,
-        mipmap_filter: wgpu::FilterMode::Nearest,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        anisotropy_clamp: MAX_ANISOTROPY,
         ..Default::default()
     });
 
-    let mut texture_views: Vec<TextureView> = Vec::new();
+    let block_definitions = resource_packs.block_definitions()?;
+    let mut blocks: Vec<Block> = Block::ALL
+        .into_iter()
+        .filter(|block| !matches!(block, Block::AIR))
+        .collect();
+    blocks.sort_by_key(|block| block.texture_index());
 
-    for file in TEXTURES {
-        let img = image::load_from_memory(fs::read(file)?.as_slice())?;
-        let dimensions = img.dimensions();
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
+    let mut images = Vec::with_capacity(blocks.len());
+    let mut animations = [TextureAnimation::zeroed(); MAX_TEXTURES];
+    for block in blocks {
+        let texture = match block_definitions.get(block.name()) {
+            Some(definition) => definition.texture.as_str(),
+            None => {
+                log::warn!("No definition for block {}", block.name());
+                block.name()
+            }
         };
+        let img = load_image(resource_packs, texture)?;
 
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some(&("texture ".to_owned() + file)),
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-
-        queue.write_texture(
-            texture.as_image_copy(),
-            &img.to_rgba8(),
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
+        let metadata_file = format!("textures/{texture}.toml");
+        let first_layer = images.len() as u32;
+        let animation = match resource_packs.read(&metadata_file)? {
+            Some(contents) => {
+                TextureAnimation::parse(std::str::from_utf8(&contents)?, &img, first_layer)
+                    .with_context(|| format!("Invalid animation metadata {metadata_file}"))?
+            }
+            None => TextureAnimation::still(first_layer),
+        };
+
+        if animation.frame_count == 1 {
+            images.push(img);
+        } else {
+            let frame_size = img.width();
+            for frame in 0..animation.frame_count {
+                images.push(
+                    image::imageops::crop_imm(&img, 0, frame * frame_size, frame_size, frame_size)
+                        .to_image(),
+                );
+            }
+        }
+        animations[block.texture_index() as usize] = animation;
+    }
+
+    let max_layers = device.limits().max_texture_array_layers as usize;
+    if images.len() > max_layers {
+        bail!(
+            "{} texture frames exceed the limit of {max_layers} texture array layers",
+            images.len()
         );
+    }
 
-        texture_views.push(texture.create_view(&wgpu::TextureViewDescriptor::default()));
+    let animation_uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("texture animation uniform buffer"),
+        contents: bytemuck::cast_slice(&animations),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    // Every layer of a texture array has the same size
+    let texture_size = images
+        .iter()
+        .map(|img| img.width().max(img.height()))
+        .fold(MIN_TEXTURE_SIZE, u32::max);
+    let mip_level_count = texture_size.ilog2() + 1;
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("block texture array"),
+        size: wgpu::Extent3d {
+            width: texture_size,
+            height: texture_size,
+            depth_or_array_layers: images.len() as u32,
+        },
+        mip_level_count,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    for (layer, img) in images.iter().enumerate() {
+        // Nearest neighbour keeps pixel art textures crisp
+        let mut mip = image::imageops::resize(img, texture_size, texture_size, FilterType::Nearest);
+
+        for mip_level in 0..mip_level_count {
+            write_layer(queue, &texture, layer as u32, mip_level, &mip);
+
+            let mip_size = (mip.width() / 2).max(1);
+            mip = image::imageops::resize(&mip, mip_size, mip_size, FilterType::Triangle);
+        }
     }
 
-    let texture_view_refs: Vec<&TextureView> = texture_views.iter().collect();
+    let texture_view = texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    });
 
     let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: &texture_bind_group_layout,
+        layout: texture_bind_group_layout,
         entries: &[
             wgpu::BindGroupEntry {
                 binding: 0,
-                resource: wgpu::BindingResource::TextureViewArray(&texture_view_refs),
+                resource: wgpu::BindingResource::TextureView(&texture_view),
             },
             wgpu::BindGroupEntry {
                 binding: 1,
                 resource: wgpu::BindingResource::Sampler(&sampler),
             },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: animation_uniform.as_entire_binding(),
+            },
         ],
         label: Some("texture bind group"),
     });
 
-    Ok((texture_bind_group_layout, texture_bind_group))
+    Ok(texture_bind_group)
+}
+
+/// Depth buffer for rendering at the given size, in the format all render pipelines use.
+pub fn create_depth_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("depth texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Depth32Float,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let depth_texture_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    (depth_texture, depth_texture_view)
+}
+
+/// Load `textures/<texture>.png`, or a checkerboard placeholder if no pack contains it.
+fn load_image(resource_packs: &ResourcePacks, texture: &str) -> Result<RgbaImage> {
+    let file = format!("textures/{texture}.png");
+    match resource_packs.read(&file)? {
+        Some(contents) => Ok(image::load_from_memory(&contents)
+            .with_context(|| format!("Failed to load texture {file}"))?
+            .to_rgba8()),
+        None => {
+            log::warn!("Missing texture {file}, using placeholder");
+            Ok(checkerboard())
+        }
+    }
+}
+
+/// Magenta and black checkerboard, the placeholder for missing textures.
+fn checkerboard() -> RgbaImage {
+    RgbaImage::from_fn(
+        2 * CHECKERBOARD_CELL_SIZE,
+        2 * CHECKERBOARD_CELL_SIZE,
+        |x, y| {
+            if (x / CHECKERBOARD_CELL_SIZE + y / CHECKERBOARD_CELL_SIZE).is_multiple_of(2) {
+                Rgba([255, 0, 255, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        },
+    )
+}
+
+/// Upload a single mip level of one layer of a texture array.
+fn write_layer(
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    layer: u32,
+    mip_level: u32,
+    img: &RgbaImage,
+) {
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level,
+            origin: wgpu::Origin3d {
+                x: 0,
+                y: 0,
+                z: layer,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        img,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * img.width()),
+            rows_per_image: Some(img.height()),
+        },
+        wgpu::Extent3d {
+            width: img.width(),
+            height: img.height(),
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_animation_metadata() {
+        let strip = RgbaImage::new(16, 64);
+        assert_eq!(
+            TextureAnimation::parse("frame_time = 0.25", &strip, 3).unwrap(),
+            TextureAnimation {
+                first_layer: 3,
+                frame_count: 4,
+                frame_time: 0.25,
+                interpolate: 0,
+            }
+        );
+
+        let animation =
+            TextureAnimation::parse("frame_time = 2\ninterpolate = true", &strip, 0).unwrap();
+        assert_eq!(animation.frame_time, 2.0);
+        assert_eq!(animation.interpolate, 1);
+
+        // Partial frames at the end are cut off, square textures have a single frame
+        let animation = TextureAnimation::parse("frame_time = 1", &RgbaImage::new(16, 40), 0);
+        assert_eq!(animation.unwrap().frame_count, 2);
+        let animation = TextureAnimation::parse("frame_time = 1", &RgbaImage::new(16, 16), 0);
+        assert_eq!(animation.unwrap().frame_count, 1);
+
+        for invalid in [
+            "frame_time = 0",
+            "frame_time = -1.5",
+            "frame_time = nan",
+            "frame_time = inf",
+            "interpolate = true",
+            "frame_time = \"fast\"",
+        ] {
+            assert!(
+                TextureAnimation::parse(invalid, &strip, 0).is_err(),
+                "{invalid} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn missing_textures_use_a_checkerboard() {
+        let packs = ResourcePacks::embedded();
+        assert_eq!(load_image(&packs, "missing").unwrap(), checkerboard());
+        assert_ne!(load_image(&packs, "stone").unwrap(), checkerboard());
+    }
 }
@@ -1,37 +1,80 @@
+mod debug_overlay;
+mod fixed_timestep;
 mod frametime_metrics;
 
 use std::{
     collections::HashSet,
-    iter,
+    fs, io, mem,
+    path::{Path, PathBuf},
+    process,
     sync::{Arc, Mutex},
-    time::Instant,
+    thread,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
+use anyhow::{Context, Result};
+use image::RgbaImage;
 use wgpu::{
-    Backends, Color, CommandEncoderDescriptor, CompositeAlphaMode, Device, DeviceDescriptor,
-    Extent3d, Features, Instance, InstanceDescriptor, Limits, LoadOp, Operations, PowerPreference,
-    PresentMode, Queue, RenderPassColorAttachment, RenderPassDepthStencilAttachment,
-    RenderPassDescriptor, RequestAdapterOptions, StoreOp, Surface, SurfaceConfiguration,
-    SurfaceError, Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
-    TextureView, TextureViewDescriptor,
+    Backends, CommandEncoderDescriptor, CompositeAlphaMode, Device, DeviceDescriptor, Features,
+    Instance, InstanceDescriptor, Limits, PowerPreference, PresentMode, RequestAdapterOptions,
+    Surface, SurfaceConfiguration, SurfaceError, Texture, TextureUsages, TextureView,
+    TextureViewDescriptor,
 };
 use winit::{
     application::ApplicationHandler,
     dpi,
     event::*,
     event_loop::{ActiveEventLoop, EventLoop},
-    keyboard::{KeyCode, PhysicalKey},
+    keyboard::PhysicalKey,
     window::{CursorGrabMode, Window, WindowId},
 };
 
-use crate::{renderer::WorldRenderer, window::frametime_metrics::FrameTimeMetrics, world::World};
+use crate::{
+    input::{
+        recording::{InputEvent, InputRecorder, InputReplay, TimedInputEvent},
+        Action, Input, InputMap, INPUT_CONFIG_FILE,
+    },
+    options::Options,
+    renderer::{
+        text_renderer::{measure_text, TextStyle},
+        WorldRenderer, REACH_DISTANCE,
+    },
+    resources::{ResourcePacks, RESOURCE_PACK_DIR},
+    texture,
+    window::{
+        debug_overlay::{DebugInfo, DebugOverlay},
+        fixed_timestep::FixedTimestep,
+        frametime_metrics::FrameTimeMetrics,
+    },
+    world::{blocks::Block, interaction::BlockInteraction, player::Player, time::WorldTime, World},
+};
+
+/// Real time duration of a full day in seconds
+pub const DAY_LENGTH_S: f32 = 600.0;
+/// Screenshots are saved here, relative to the working directory
+const SCREENSHOT_DIR: &str = "screenshots";
+/// Frame time exports are saved here, relative to the working directory
+const FRAME_TIME_DIR: &str = "frametimes";
+/// Simulation ticks per second
+const TICK_RATE: u32 = 60;
+/// Frames taking longer than this many ticks slow down the simulation instead of running ever
+/// more ticks to catch up
+const MAX_TICKS_PER_FRAME: u32 = 10;
 
 pub struct App {
+    world_seed: u32,
     window: Option<Arc<Window>>,
     gfx_state: Option<GfxState>,
-    pressed_keys: HashSet<KeyCode>,
+    input_map: InputMap,
+    pressed_inputs: HashSet<Input>,
+    /// Held actions, resolved from the pressed inputs or replayed
+    active_actions: HashSet<Action>,
     mouse_movement: (f64, f64),
     frametime_metrics: FrameTimeMetrics,
+    /// Records all input handled while set
+    recorder: Option<InputRecorder>,
+    /// Drives the application instead of the user's input while set
+    replay: Option<InputReplay>,
 }
 
 impl ApplicationHandler for App {
@@ -47,7 +90,13 @@ impl ApplicationHandler for App {
         );
         window.set_cursor_visible(false);
 
-        self.gfx_state = Some(pollster::block_on(GfxState::new(Arc::clone(&window))));
+        match pollster::block_on(GfxState::new(Arc::clone(&window), self.world_seed)) {
+            Ok(gfx_state) => self.gfx_state = Some(gfx_state),
+            Err(error) => {
+                log::error!("Failed to initialize graphics: {error:#}");
+                event_loop.exit();
+            }
+        }
         self.window = Some(window);
     }
 
@@ -57,13 +106,10 @@ impl ApplicationHandler for App {
         _device_id: DeviceId,
         event: DeviceEvent,
     ) {
-        match event {
-            DeviceEvent::MouseMotion { delta } => {
-                let (new_x, new_y) = delta;
-                let (old_x, old_y) = self.mouse_movement;
-                self.mouse_movement = (old_x + new_x, old_y + new_y);
-            }
-            _ => {}
+        if let DeviceEvent::MouseMotion { delta } = event {
+            let (new_x, new_y) = delta;
+            let (old_x, old_y) = self.mouse_movement;
+            self.mouse_movement = (old_x + new_x, old_y + new_y);
         }
     }
 
@@ -73,6 +119,11 @@ impl ApplicationHandler for App {
         _window_id: WindowId,
         event: WindowEvent,
     ) {
+        // Initialization failed, the event loop is shutting down
+        if self.gfx_state.is_none() {
+            return;
+        }
+
         match event {
             WindowEvent::CloseRequested => {
                 log::info!("Close button pressed, terminating");
@@ -91,14 +142,10 @@ impl ApplicationHandler for App {
                         ..
                     },
                 ..
-            } => match state {
-                ElementState::Pressed => {
-                    self.pressed_keys.insert(keycode);
-                }
-                ElementState::Released => {
-                    self.pressed_keys.remove(&keycode);
-                }
-            },
+            } => self.handle_user_input(Input::Key(keycode), state),
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.handle_user_input(Input::Mouse(button), state)
+            }
             WindowEvent::CursorEntered { .. } => {
                 let window = self.window.as_ref().unwrap();
                 window
@@ -109,11 +156,47 @@ impl ApplicationHandler for App {
             WindowEvent::RedrawRequested => {
                 let frametime_start = Instant::now();
 
-                let gfx_state = self.gfx_state.as_mut().unwrap();
+                let ticks = self.gfx_state.as_mut().unwrap().start_frame();
 
-                gfx_state.update(&self.pressed_keys, self.mouse_movement);
                 // Don't handle the same mouse input twice
-                self.mouse_movement = (0.0, 0.0);
+                let (dx, dy) = mem::take(&mut self.mouse_movement);
+                if (dx != 0.0 || dy != 0.0) && self.replay.is_none() {
+                    let (yaw, pitch) = self.input_map.mouse_rotation((dx, dy));
+                    self.record_and_apply(InputEvent::Look(yaw, pitch));
+                }
+
+                let deterministic = self.recorder.is_some() || self.replay.is_some();
+                for _ in 0..ticks {
+                    // The player collides with unloaded terrain, so recordings only replay the
+                    // same way if the simulation waits for it to load
+                    let gfx_state = self.gfx_state.as_ref().unwrap();
+                    if deterministic && !gfx_state.is_terrain_loaded() {
+                        break;
+                    }
+
+                    let tick = gfx_state.get_tick();
+                    match &mut self.replay {
+                        Some(replay) => {
+                            for event in replay.events_before(tick) {
+                                self.apply_input_event(event);
+                            }
+                        }
+                        None => self.update_active_actions(),
+                    }
+                    self.gfx_state.as_mut().unwrap().tick(&self.active_actions);
+                }
+
+                if self.replay.as_ref().is_some_and(InputReplay::is_finished) {
+                    log::info!("Replay finished, terminating");
+                    event_loop.exit();
+                }
+
+                let gfx_state = self.gfx_state.as_mut().unwrap();
+                gfx_state.show_debug_overlay(&self.frametime_metrics);
+                if let Some(prompt) = self.input_map.rebinding_prompt() {
+                    gfx_state.show_prompt(&prompt);
+                }
+                gfx_state.update();
                 match gfx_state.render() {
                     Ok(_) => {}
                     // Reconfigure the surface if it's lost or outdated
@@ -142,21 +225,114 @@ impl ApplicationHandler for App {
     }
 }
 
+impl App {
+    /// Handle a pressed or released input from the user, which is ignored while replaying.
+    fn handle_user_input(&mut self, input: Input, state: ElementState) {
+        if self.replay.is_some() {
+            return;
+        }
+
+        match state {
+            ElementState::Pressed => {
+                self.pressed_inputs.insert(input);
+                if self.input_map.handle_rebinding(input) {
+                    return;
+                }
+
+                for action in self.input_map.actions(input) {
+                    match action {
+                        // Only changes the bindings, which replays don't depend on
+                        Action::Rebind => self.input_map.start_rebinding(),
+                        _ => self.record_and_apply(InputEvent::Triggered(action)),
+                    }
+                }
+            }
+            ElementState::Released => {
+                self.pressed_inputs.remove(&input);
+            }
+        }
+    }
+
+    /// Start and stop held actions according to the pressed inputs.
+    fn update_active_actions(&mut self) {
+        let actions = self.input_map.active_actions(&self.pressed_inputs);
+        let started: Vec<Action> = actions.difference(&self.active_actions).copied().collect();
+        let stopped: Vec<Action> = self.active_actions.difference(&actions).copied().collect();
+
+        for action in started {
+            self.record_and_apply(InputEvent::Started(action));
+        }
+        for action in stopped {
+            self.record_and_apply(InputEvent::Stopped(action));
+        }
+    }
+
+    /// Apply an event resolved from the user's input, recording it if a recording is running.
+    fn record_and_apply(&mut self, event: InputEvent) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(TimedInputEvent {
+                tick: self.gfx_state.as_ref().unwrap().get_tick(),
+                event,
+            });
+        }
+        self.apply_input_event(event);
+    }
+
+    fn apply_input_event(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Triggered(Action::ExportFrameTimes) => self.export_frame_times(),
+            InputEvent::Triggered(action) => self.gfx_state.as_mut().unwrap().handle_action(action),
+            InputEvent::Started(action) => {
+                self.active_actions.insert(action);
+            }
+            InputEvent::Stopped(action) => {
+                self.active_actions.remove(&action);
+            }
+            InputEvent::Look(yaw, pitch) => self
+                .gfx_state
+                .as_mut()
+                .unwrap()
+                .world_renderer
+                .camera_controller
+                .rotate((yaw, pitch)),
+        }
+    }
+
+    /// Save the recent frame times as CSV to the frame time directory.
+    fn export_frame_times(&self) {
+        let path = timestamped_path(FRAME_TIME_DIR, "csv");
+        let result = fs::create_dir_all(FRAME_TIME_DIR)
+            .and_then(|_| fs::File::create(&path))
+            .and_then(|file| self.frametime_metrics.write_csv(io::BufWriter::new(file)));
+        match result {
+            Ok(()) => log::info!("Saved frame times to {}", path.display()),
+            Err(error) => log::error!("Failed to save frame times to {}: {error}", path.display()),
+        }
+    }
+}
+
 struct GfxState {
     surface: Surface<'static>,
     device: Arc<Device>,
-    queue: Arc<Queue>,
     surface_config: SurfaceConfiguration,
     depth_texture: Texture,
     depth_texture_view: TextureView,
-    clear_color: Color,
     world_renderer: WorldRenderer,
     world: Arc<Mutex<World>>,
+    world_time: WorldTime,
+    block_interaction: BlockInteraction,
+    player: Player,
+    debug_overlay: DebugOverlay,
+    timestep: FixedTimestep,
+    /// Number of simulated ticks
+    tick: u64,
+    /// Place a block with the next tick
+    place_requested: bool,
     last_update: Instant,
 }
 
 impl GfxState {
-    async fn new(window: Arc<Window>) -> GfxState {
+    async fn new(window: Arc<Window>, world_seed: u32) -> Result<GfxState> {
         let size: dpi::PhysicalSize<u32> = window.inner_size();
 
         let instance = Instance::new(InstanceDescriptor {
@@ -164,7 +340,7 @@ impl GfxState {
             ..Default::default()
         });
 
-        let surface = instance.create_surface(window).unwrap();
+        let surface = instance.create_surface(window)?;
 
         let adapter = instance
             .request_adapter(&RequestAdapterOptions {
@@ -173,21 +349,22 @@ impl GfxState {
                 force_fallback_adapter: false,
             })
             .await
-            .unwrap();
+            .context("No suitable graphics adapter found")?;
+        let adapter_info = adapter.get_info();
+        log::info!("Using graphics adapter {adapter_info:?}");
 
         let (device, queue) = adapter
             .request_device(
                 &DeviceDescriptor {
                     label: None,
                     required_limits: Limits::default(),
-                    required_features: Features::TEXTURE_BINDING_ARRAY
-                        | Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING
-                        | Features::POLYGON_MODE_LINE,
+                    // For profiling render passes, if available
+                    required_features: adapter.features() & Features::TIMESTAMP_QUERY,
                 },
                 None,
             )
             .await
-            .unwrap();
+            .context("Failed to create graphics device")?;
 
         let surface_caps = surface.get_capabilities(&adapter);
         // Use sRGB surface
@@ -210,60 +387,46 @@ impl GfxState {
         };
 
         let (depth_texture, depth_texture_view) =
-            GfxState::create_depth_texture(&device, surface_config.width, surface_config.height);
+            texture::create_depth_texture(&device, surface_config.width, surface_config.height);
 
         let device = Arc::new(device);
         let queue = Arc::new(queue);
 
-        let world = Arc::new(Mutex::new(World::new(0)));
+        let world = Arc::new(Mutex::new(World::new(world_seed)));
+
+        let world_time = WorldTime::new(0.3, DAY_LENGTH_S);
 
-        let mut world_renderer =
-            WorldRenderer::new(Arc::clone(&device), Arc::clone(&queue), &surface_config);
+        let resource_packs = ResourcePacks::load(Path::new(RESOURCE_PACK_DIR))?;
+        let block_interaction = BlockInteraction::new(&resource_packs.block_definitions()?);
+
+        let mut world_renderer = WorldRenderer::new(
+            Arc::clone(&device),
+            queue,
+            surface_config.format,
+            (surface_config.width, surface_config.height),
+            &resource_packs,
+            world_seed,
+        )?;
+        let player = Player::new(world_renderer.camera_controller.get_position());
         world_renderer.update(Arc::clone(&world));
 
-        Self {
+        Ok(Self {
             surface,
             device,
-            queue,
             surface_config,
             depth_texture,
             depth_texture_view,
-            clear_color: Color {
-                r: 135.0 / 255.0,
-                g: 206.0 / 255.0,
-                b: 235.0 / 255.0,
-                a: 0.0,
-            },
             world_renderer,
             world,
+            world_time,
+            block_interaction,
+            player,
+            debug_overlay: DebugOverlay::new(&adapter_info),
+            timestep: FixedTimestep::new(TICK_RATE, MAX_TICKS_PER_FRAME),
+            tick: 0,
+            place_requested: false,
             last_update: Instant::now(),
-        }
-    }
-
-    pub fn create_depth_texture(
-        device: &Device,
-        width: u32,
-        height: u32,
-    ) -> (Texture, TextureView) {
-        let size = Extent3d {
-            width,
-            height,
-            depth_or_array_layers: 1,
-        };
-        let depth_texture_descriptor = TextureDescriptor {
-            label: Some("depth texture"),
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Depth32Float,
-            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        };
-        let depth_texture = device.create_texture(&depth_texture_descriptor);
-        let depth_texture_view = depth_texture.create_view(&TextureViewDescriptor::default());
-
-        (depth_texture, depth_texture_view)
+        })
     }
 
     pub fn resize(&mut self, new_size: dpi::PhysicalSize<u32>) {
@@ -272,11 +435,9 @@ impl GfxState {
             self.surface_config.height = new_size.height;
             self.surface.configure(&self.device, &self.surface_config);
 
-            self.world_renderer
-                .camera_controller
-                .set_aspect_ratio(new_size.width as f32 / new_size.height as f32);
+            self.world_renderer.resize(new_size.width, new_size.height);
 
-            let (depth_texture, depth_texture_view) = GfxState::create_depth_texture(
+            let (depth_texture, depth_texture_view) = texture::create_depth_texture(
                 &self.device,
                 self.surface_config.width,
                 self.surface_config.height,
@@ -286,17 +447,180 @@ impl GfxState {
         }
     }
 
-    fn update(&mut self, pressed_keys: &HashSet<KeyCode>, mouse_movement: (f64, f64)) {
+    /// Handle actions that are triggered once when pressed instead of being held down.
+    fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::IncreaseRenderDistance => self
+                .world_renderer
+                .set_render_distance(self.world_renderer.get_render_distance() + 1),
+            Action::DecreaseRenderDistance => self
+                .world_renderer
+                .set_render_distance(self.world_renderer.get_render_distance() - 1),
+            Action::CycleFogMode => {
+                let environment = &mut self.world_renderer.environment;
+                environment.fog_mode = environment.fog_mode.next();
+                log::info!("Fog mode: {:?}", environment.fog_mode);
+            }
+            Action::SkipTime => {
+                // Skip ahead an eighth of a day
+                self.world_time
+                    .set_time_of_day(self.world_time.get_time_of_day() + 0.125);
+                log::info!("Time of day: {:.3}", self.world_time.get_time_of_day());
+            }
+            Action::CycleShadowQuality => self
+                .world_renderer
+                .set_shadow_quality(self.world_renderer.get_shadow_quality().next()),
+            Action::PauseTime => {
+                self.world_time.paused = !self.world_time.paused;
+                log::info!("Time paused: {}", self.world_time.paused);
+            }
+            Action::ToggleFlying => {
+                self.player.movement_mode = self.player.movement_mode.next();
+                log::info!("Movement mode: {:?}", self.player.movement_mode);
+            }
+            Action::PlaceBlock => self.place_requested = true,
+            Action::Screenshot => self.take_screenshot(),
+            Action::ToggleDebugOverlay => self.debug_overlay.toggle(),
+            _ => {
+                if let Some(index) = action.block_selection_index() {
+                    let block = Block::ALL[index + 1];
+                    self.block_interaction.selected_block = block;
+                    log::info!("Selected block: {}", block.name());
+                }
+            }
+        }
+    }
+
+    /// Measure the time since the last frame and return the number of ticks to simulate for it.
+    fn start_frame(&mut self) -> u32 {
         let now = Instant::now();
-        self.world_renderer.camera_controller.handle_input(
-            pressed_keys,
-            mouse_movement,
-            now.duration_since(self.last_update).as_secs_f32(),
-        );
+        let frame_time = now.duration_since(self.last_update);
+        self.last_update = now;
+
+        self.world_renderer.environment.time += frame_time.as_secs_f32();
+        self.timestep.advance(frame_time)
+    }
+
+    /// Prepare rendering the simulated state, interpolated towards the next tick.
+    fn update(&mut self) {
+        let alpha = self.timestep.alpha();
+        self.world_renderer
+            .camera_controller
+            .set_position(self.player.get_eye_position(alpha));
+
+        self.world_renderer
+            .environment
+            .set_world_time(&self.world_time);
+
+        self.world_renderer.break_progress = self.block_interaction.break_progress();
 
         self.world_renderer.update(Arc::clone(&self.world));
+    }
 
-        self.last_update = now;
+    /// Show the debug overlay with the next frame, if it's toggled on.
+    fn show_debug_overlay(&mut self, frametime_metrics: &FrameTimeMetrics) {
+        let world_renderer = &mut self.world_renderer;
+        let (loaded_chunks, meshed_chunks) = world_renderer.get_chunk_counts();
+        let info = DebugInfo {
+            frametime_ms: frametime_metrics.last_sample_frametime_ms,
+            frametime_stats: frametime_metrics.stats,
+            camera_position: world_renderer.camera_controller.get_position(),
+            camera_chunk: world_renderer.get_camera_chunk(),
+            camera_rotation: world_renderer.camera_controller.get_rotation(),
+            loaded_chunks,
+            meshed_chunks,
+            instance_count: world_renderer.get_instance_count(),
+            pending_jobs: world_renderer.get_pending_jobs(),
+            target: world_renderer.get_target(),
+            gpu_timings: world_renderer.get_gpu_timings().cloned(),
+        };
+        self.debug_overlay.show(
+            &mut world_renderer.text_renderer,
+            &info,
+            frametime_metrics.history(),
+        );
+    }
+
+    /// Show `text` centered at the top of the screen with the next frame.
+    fn show_prompt(&mut self, text: &str) {
+        let style = TextStyle {
+            scale: 2.0,
+            ..Default::default()
+        };
+        let (width, height) = measure_text(text, style.scale);
+        let x = ((self.surface_config.width as f32 - width) / 2.0).round();
+        let y = 32.0;
+        const PADDING: f32 = 8.0;
+
+        let text_renderer = &mut self.world_renderer.text_renderer;
+        text_renderer.queue_rectangle(
+            (x - PADDING, y - PADDING),
+            (width + 2.0 * PADDING, height + 2.0 * PADDING),
+            [0.0, 0.0, 0.0, 0.5],
+        );
+        text_renderer.queue_text(text, (x, y), style);
+    }
+
+    /// Advance the simulation by one fixed tick.
+    fn tick(&mut self, actions: &HashSet<Action>) {
+        let tick_s = self.timestep.tick_duration_s();
+        let camera_controller = &self.world_renderer.camera_controller;
+        let mut world = self.world.lock().unwrap();
+
+        self.player.update(
+            &world,
+            actions,
+            camera_controller.get_horizontal_direction(),
+            tick_s,
+        );
+
+        // Target from the simulated position instead of the rendered one, so replaying input
+        // edits the same blocks
+        let target = world.raycast(
+            self.player.get_eye_position(1.0),
+            camera_controller.get_direction(),
+            REACH_DISTANCE,
+        );
+        self.block_interaction
+            .update(target, actions.contains(&Action::BreakBlock), tick_s);
+        if mem::take(&mut self.place_requested) {
+            self.block_interaction
+                .place(target, |position| self.player.intersects_block(position));
+        }
+        let changed = self.block_interaction.apply_edits(&mut world);
+        drop(world);
+        if changed {
+            self.world_renderer.invalidate_meshes();
+        }
+
+        self.world_time.advance(tick_s);
+        self.tick += 1;
+    }
+
+    /// Save a screenshot of the current view to the screenshot directory.
+    fn take_screenshot(&self) {
+        match self
+            .world_renderer
+            .screenshot(self.surface_config.width, self.surface_config.height)
+        {
+            // Encoding takes a while, so don't stall the game for it
+            Ok(image) => {
+                thread::spawn(move || save_screenshot(&image));
+            }
+            Err(error) => log::error!("Failed to take screenshot: {error:#}"),
+        }
+    }
+
+    /// Whether the terrain the player can collide with within the next ticks is loaded.
+    fn is_terrain_loaded(&self) -> bool {
+        self.world
+            .lock()
+            .unwrap()
+            .is_loaded_around(self.player.get_eye_position(1.0), 1)
+    }
+
+    fn get_tick(&self) -> u64 {
+        self.tick
     }
 
     fn render(&mut self) -> Result<(), SurfaceError> {
@@ -311,48 +635,69 @@ impl GfxState {
                 label: Some("render encoder"),
             });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                label: Some("render rass"),
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: Operations {
-                        load: LoadOp::Clear(self.clear_color),
-                        store: StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture_view,
-                    depth_ops: Some(Operations {
-                        load: LoadOp::Clear(1.0),
-                        store: StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
-
-            self.world_renderer.render(&mut render_pass);
-        }
+        self.world_renderer
+            .render_frame(&mut encoder, &view, &self.depth_texture_view);
 
-        self.queue.submit(iter::once(encoder.finish()));
+        self.world_renderer.submit_frame(encoder);
         output.present();
 
         Ok(())
     }
 }
 
-pub async fn run() {
+/// Path in `directory` named after the current time, for files saved at runtime.
+fn timestamped_path(directory: &str, extension: &str) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    Path::new(directory).join(format!("{timestamp}.{extension}"))
+}
+
+fn save_screenshot(image: &RgbaImage) {
+    let path = timestamped_path(SCREENSHOT_DIR, "png");
+
+    let result = fs::create_dir_all(SCREENSHOT_DIR)
+        .map_err(anyhow::Error::from)
+        .and_then(|_| Ok(image.save(&path)?));
+    match result {
+        Ok(()) => log::info!("Saved screenshot to {}", path.display()),
+        Err(error) => log::error!("Failed to save screenshot to {}: {error:#}", path.display()),
+    }
+}
+
+pub async fn run(options: Options) {
+    let recorder = options.record.map(|path| {
+        InputRecorder::create(&path, options.scene.seed).unwrap_or_else(|error| {
+            log::error!("{error:#}");
+            process::exit(1);
+        })
+    });
+    let replay = options.replay.map(|path| {
+        InputReplay::load(&path, options.scene.seed).unwrap_or_else(|error| {
+            log::error!("Failed to load input replay: {error:#}");
+            process::exit(1);
+        })
+    });
+
+    let input_map = InputMap::load(Path::new(INPUT_CONFIG_FILE)).unwrap_or_else(|error| {
+        log::error!("Failed to load input bindings, using the defaults: {error:#}");
+        InputMap::with_defaults(Path::new(INPUT_CONFIG_FILE))
+    });
+
     let event_loop: EventLoop<()> = EventLoop::new().unwrap();
     event_loop
         .run_app(&mut App {
+            world_seed: options.scene.seed,
             window: None,
             gfx_state: None,
-            pressed_keys: Default::default(),
+            input_map,
+            pressed_inputs: Default::default(),
+            active_actions: Default::default(),
             mouse_movement: Default::default(),
             frametime_metrics: FrameTimeMetrics::new(1000),
+            recorder,
+            replay,
         })
         .unwrap();
 }
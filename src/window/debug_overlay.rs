@@ -0,0 +1,273 @@
+use std::{fmt::Write, time::Duration};
+
+use glam::Vec3;
+use wgpu::AdapterInfo;
+
+use crate::{
+    renderer::{
+        gpu_profiler::{self, GpuTimings},
+        text_renderer::{measure_text, TextRenderer, TextStyle},
+    },
+    window::frametime_metrics::FrameTimeStats,
+    world::RaycastHit,
+};
+
+/// Distance of the overlay from the top left corner of the screen in pixels
+const MARGIN: f32 = 8.0;
+const PADDING: f32 = 4.0;
+const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.5];
+
+/// Frames shown in the frame time graph, one bar each
+const GRAPH_FRAMES: usize = 240;
+const GRAPH_BAR_WIDTH: f32 = 2.0;
+const GRAPH_HEIGHT: f32 = 100.0;
+/// Frame time at the top of the graph, longer frames are cut off
+const GRAPH_MAX_MS: f32 = 50.0;
+/// Frame times of 60 and 30 FPS, marked with lines in the graph. Bars are colored by the first
+/// of these they don't exceed, or red.
+const GRAPH_THRESHOLDS_MS: [(f32, [f32; 4]); 2] = [
+    (1000.0 / 60.0, [0.2, 0.9, 0.2, 0.9]),
+    (1000.0 / 30.0, [0.9, 0.9, 0.2, 0.9]),
+];
+const GRAPH_SLOW_COLOR: [f32; 4] = [0.9, 0.2, 0.2, 0.9];
+const GRAPH_LINE_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.5];
+
+/// State of the game and renderer shown in the debug overlay.
+pub struct DebugInfo {
+    /// Average frame time of the last sampling interval
+    pub frametime_ms: f64,
+    /// Statistics of the most recent frames
+    pub frametime_stats: Option<FrameTimeStats>,
+    pub camera_position: Vec3,
+    pub camera_chunk: (i32, i32, i32),
+    /// Camera rotation in half turns
+    pub camera_rotation: (f32, f32),
+    pub loaded_chunks: usize,
+    pub meshed_chunks: usize,
+    pub instance_count: usize,
+    /// Chunk loading and meshing jobs still running
+    pub pending_jobs: usize,
+    pub target: Option<RaycastHit>,
+    /// Missing without support for timestamp queries
+    pub gpu_timings: Option<GpuTimings>,
+}
+
+/// Text overlay with debug information, toggled with the `toggle_debug_overlay` action.
+pub struct DebugOverlay {
+    visible: bool,
+    adapter: String,
+}
+
+impl DebugOverlay {
+    pub fn new(adapter_info: &AdapterInfo) -> Self {
+        DebugOverlay {
+            visible: false,
+            adapter: format!("{} ({:?})", adapter_info.name, adapter_info.backend),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Queue the overlay for the next frame if it's visible, with a graph of the most recent
+    /// `frametimes`, oldest first.
+    pub fn show(
+        &self,
+        text_renderer: &mut TextRenderer,
+        info: &DebugInfo,
+        frametimes: impl ExactSizeIterator<Item = Duration>,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        let text = self.format(info);
+        let style = TextStyle {
+            scale: 2.0,
+            ..Default::default()
+        };
+        let (width, height) = measure_text(&text, style.scale);
+        text_renderer.queue_rectangle(
+            (MARGIN - PADDING, MARGIN - PADDING),
+            (width + 2.0 * PADDING, height + 2.0 * PADDING),
+            BACKGROUND_COLOR,
+        );
+        text_renderer.queue_text(&text, (MARGIN, MARGIN), style);
+
+        let graph_top = MARGIN + height + 2.0 * PADDING + MARGIN;
+        DebugOverlay::show_graph(text_renderer, (MARGIN, graph_top), frametimes);
+    }
+
+    /// Bar graph of `frametimes` with its top left corner at `position`, the newest frame on the
+    /// right.
+    fn show_graph(
+        text_renderer: &mut TextRenderer,
+        position: (f32, f32),
+        frametimes: impl ExactSizeIterator<Item = Duration>,
+    ) {
+        let (x, y) = position;
+        let width = GRAPH_FRAMES as f32 * GRAPH_BAR_WIDTH;
+        let bottom = y + GRAPH_HEIGHT;
+        let height_for =
+            |frametime_ms: f32| frametime_ms.min(GRAPH_MAX_MS) / GRAPH_MAX_MS * GRAPH_HEIGHT;
+
+        text_renderer.queue_rectangle(
+            (x - PADDING, y - PADDING),
+            (width + 2.0 * PADDING, GRAPH_HEIGHT + 2.0 * PADDING),
+            BACKGROUND_COLOR,
+        );
+
+        let skipped = frametimes.len().saturating_sub(GRAPH_FRAMES);
+        let shown = frametimes.len() - skipped;
+        // Right align, so the graph scrolls to the left
+        let first_bar_x = x + (GRAPH_FRAMES - shown) as f32 * GRAPH_BAR_WIDTH;
+        for (index, frametime) in frametimes.skip(skipped).enumerate() {
+            let frametime_ms = frametime.as_secs_f32() * 1000.0;
+            let color = GRAPH_THRESHOLDS_MS
+                .iter()
+                .find(|(threshold_ms, _)| frametime_ms <= *threshold_ms)
+                .map_or(GRAPH_SLOW_COLOR, |(_, color)| *color);
+            let bar_height = height_for(frametime_ms);
+            text_renderer.queue_rectangle(
+                (
+                    first_bar_x + index as f32 * GRAPH_BAR_WIDTH,
+                    bottom - bar_height,
+                ),
+                (GRAPH_BAR_WIDTH, bar_height),
+                color,
+            );
+        }
+
+        for (threshold_ms, _) in GRAPH_THRESHOLDS_MS {
+            text_renderer.queue_rectangle(
+                (x, bottom - height_for(threshold_ms)),
+                (width, 1.0),
+                GRAPH_LINE_COLOR,
+            );
+        }
+    }
+
+    fn format(&self, info: &DebugInfo) -> String {
+        let fps = if info.frametime_ms > 0.0 {
+            1000.0 / info.frametime_ms
+        } else {
+            0.0
+        };
+        let Vec3 { x, y, z } = info.camera_position;
+        let (u, v, w) = info.camera_chunk;
+        let (yaw, pitch) = info.camera_rotation;
+
+        let mut text = String::new();
+        // Writing to a string can't fail
+        let _ = writeln!(text, "{fps:.0} FPS ({:.2} ms)", info.frametime_ms);
+        if let Some(stats) = info.frametime_stats {
+            let _ = writeln!(
+                text,
+                "Frame time: min {:.2}, avg {:.2}, max {:.2} ms",
+                stats.min_ms, stats.average_ms, stats.max_ms
+            );
+            let _ = writeln!(
+                text,
+                "Percentiles: 50% {:.2}, 95% {:.2}, 99% {:.2} ms",
+                stats.median_ms, stats.p95_ms, stats.p99_ms
+            );
+            let _ = writeln!(
+                text,
+                "Lows: 1% {:.0} FPS, 0.1% {:.0} FPS",
+                1000.0 / stats.low_1_percent_ms,
+                1000.0 / stats.low_0_1_percent_ms
+            );
+        }
+        let _ = writeln!(text, "Position: {x:.2} / {y:.2} / {z:.2}");
+        let _ = writeln!(text, "Chunk: {u} / {v} / {w}");
+        let _ = writeln!(text, "Yaw: {:.1}, pitch: {:.1}", yaw * 180.0, pitch * 180.0);
+        let _ = writeln!(
+            text,
+            "Chunks: {} loaded, {} meshed",
+            info.loaded_chunks, info.meshed_chunks
+        );
+        let _ = writeln!(text, "Instances: {}", info.instance_count);
+        let _ = writeln!(text, "Pending jobs: {}", info.pending_jobs);
+        let _ = writeln!(text, "Adapter: {}", self.adapter);
+        match &info.gpu_timings {
+            Some(timings) => {
+                let _ = writeln!(text, "GPU: {}", gpu_profiler::format_timings(timings));
+            }
+            None => text.push_str("GPU: unavailable\n"),
+        }
+        match info.target {
+            Some(RaycastHit {
+                position: (x, y, z),
+                face,
+                block,
+            }) => {
+                let _ = write!(
+                    text,
+                    "Target: {} at {x} / {y} / {z}, {face:?}",
+                    block.name()
+                );
+            }
+            None => text.push_str("Target: none"),
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wgpu::{Backend, DeviceType};
+
+    use super::*;
+    use crate::world::blocks::{Block, Direction};
+
+    #[test]
+    fn formats_debug_info() {
+        let overlay = DebugOverlay::new(&AdapterInfo {
+            name: "llvmpipe".to_owned(),
+            vendor: 0,
+            device: 0,
+            device_type: DeviceType::Cpu,
+            driver: String::new(),
+            driver_info: String::new(),
+            backend: Backend::Gl,
+        });
+        let info = DebugInfo {
+            frametime_ms: 0.0,
+            frametime_stats: FrameTimeStats::new([2, 4, 6, 10].map(Duration::from_millis)),
+            camera_position: Vec3::new(1.5, 70.0, -20.25),
+            camera_chunk: (0, 2, -1),
+            camera_rotation: (1.5, -0.25),
+            loaded_chunks: 400,
+            meshed_chunks: 300,
+            instance_count: 12345,
+            pending_jobs: 1,
+            target: Some(RaycastHit {
+                position: (1, 68, -21),
+                face: Direction::Y,
+                block: Block::STONE,
+            }),
+            gpu_timings: None,
+        };
+
+        let text = overlay.format(&info);
+        assert_eq!(
+            text.lines().collect::<Vec<_>>(),
+            [
+                "0 FPS (0.00 ms)",
+                "Frame time: min 2.00, avg 5.50, max 10.00 ms",
+                "Percentiles: 50% 4.00, 95% 10.00, 99% 10.00 ms",
+                "Lows: 1% 100 FPS, 0.1% 100 FPS",
+                "Position: 1.50 / 70.00 / -20.25",
+                "Chunk: 0 / 2 / -1",
+                "Yaw: 270.0, pitch: -45.0",
+                "Chunks: 400 loaded, 300 meshed",
+                "Instances: 12345",
+                "Pending jobs: 1",
+                "Adapter: llvmpipe (Gl)",
+                "GPU: unavailable",
+                "Target: stone at 1 / 68 / -21, Y",
+            ]
+        );
+    }
+}
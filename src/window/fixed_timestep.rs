@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+/// Splits variable frame times into simulation ticks of a fixed duration, so the simulation
+/// behaves the same regardless of the frame rate.
+pub struct FixedTimestep {
+    tick_duration: Duration,
+    /// Frame time not yet simulated, always less than a tick after `advance`
+    accumulator: Duration,
+    /// At most this many ticks are run per frame, so the simulation can't fall further and
+    /// further behind when ticks take longer than real time
+    max_ticks_per_frame: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(tick_rate: u32, max_ticks_per_frame: u32) -> Self {
+        FixedTimestep {
+            tick_duration: Duration::from_secs(1) / tick_rate,
+            accumulator: Duration::ZERO,
+            max_ticks_per_frame,
+        }
+    }
+
+    /// Add the duration of a frame and return the number of ticks to simulate for it.
+    pub fn advance(&mut self, frame_time: Duration) -> u32 {
+        self.accumulator += frame_time;
+
+        let ticks = (self.accumulator.as_nanos() / self.tick_duration.as_nanos()) as u32;
+        if ticks > self.max_ticks_per_frame {
+            // Drop the time that can't be caught up with
+            self.accumulator = Duration::ZERO;
+            return self.max_ticks_per_frame;
+        }
+
+        self.accumulator -= self.tick_duration * ticks;
+        ticks
+    }
+
+    pub fn tick_duration_s(&self) -> f32 {
+        self.tick_duration.as_secs_f32()
+    }
+
+    /// Progress from the last simulated tick towards the next one, from 0 to 1. Used to
+    /// interpolate the rendered state between the last two ticks.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.tick_duration.as_secs_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulates_partial_ticks() {
+        let mut timestep = FixedTimestep::new(20, 10);
+        assert_eq!(timestep.advance(Duration::from_millis(30)), 0);
+        assert!((timestep.alpha() - 0.6).abs() < 1e-6);
+        assert_eq!(timestep.advance(Duration::from_millis(30)), 1);
+        assert!((timestep.alpha() - 0.2).abs() < 1e-6);
+        assert_eq!(timestep.advance(Duration::from_millis(100)), 2);
+    }
+
+    #[test]
+    fn limits_ticks_per_frame() {
+        let mut timestep = FixedTimestep::new(20, 10);
+        assert_eq!(timestep.advance(Duration::from_secs(5)), 10);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+}
@@ -1,41 +1,178 @@
 use std::{
     collections::VecDeque,
+    io::{self, Write},
     time::{Duration, Instant},
 };
 
+/// Number of most recent frames kept for statistics, the frame time graph and exports. Enough
+/// for the 0.1% lows to cover more than a single frame.
+pub const HISTORY_LENGTH: usize = 2000;
+
+/// Statistics of a series of frame times, all in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameTimeStats {
+    pub average_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+    pub median_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    /// Average of the slowest 1% of frames
+    pub low_1_percent_ms: f64,
+    /// Average of the slowest 0.1% of frames
+    pub low_0_1_percent_ms: f64,
+}
+
+impl FrameTimeStats {
+    /// Statistics of `frametimes`, or `None` if there are none.
+    pub fn new(frametimes: impl IntoIterator<Item = Duration>) -> Option<Self> {
+        let mut sorted_ms: Vec<f64> = frametimes
+            .into_iter()
+            .map(|frametime| frametime.as_secs_f64() * 1000.0)
+            .collect();
+        if sorted_ms.is_empty() {
+            return None;
+        }
+        sorted_ms.sort_by(f64::total_cmp);
+
+        let count = sorted_ms.len();
+        // Nearest rank percentile
+        let percentile = |percent: f64| {
+            let rank = (percent / 100.0 * count as f64).ceil() as usize;
+            sorted_ms[rank.clamp(1, count) - 1]
+        };
+        let average = |frametimes: &[f64]| frametimes.iter().sum::<f64>() / frametimes.len() as f64;
+        // Always includes at least the slowest frame
+        let low = |percent: f64| {
+            let slowest = ((percent / 100.0 * count as f64).ceil() as usize).max(1);
+            average(&sorted_ms[count - slowest..])
+        };
+
+        Some(FrameTimeStats {
+            average_ms: average(&sorted_ms),
+            min_ms: sorted_ms[0],
+            max_ms: sorted_ms[count - 1],
+            median_ms: percentile(50.0),
+            p95_ms: percentile(95.0),
+            p99_ms: percentile(99.0),
+            low_1_percent_ms: low(1.0),
+            low_0_1_percent_ms: low(0.1),
+        })
+    }
+}
+
 pub struct FrameTimeMetrics {
+    /// Frame times of the current sampling interval
     deque: VecDeque<Duration>,
+    /// The last `HISTORY_LENGTH` frame times, oldest first
+    history: VecDeque<Duration>,
     sampling_interval_ms: u128,
     last_sample_instant: Instant,
     pub last_sample_frametime_ms: f64,
+    /// Statistics of the history, updated with each sample
+    pub stats: Option<FrameTimeStats>,
 }
 
 impl FrameTimeMetrics {
     pub fn new(sampling_interval_ms: u128) -> Self {
         FrameTimeMetrics {
             deque: VecDeque::new(),
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
             sampling_interval_ms,
             last_sample_instant: Instant::now(),
             last_sample_frametime_ms: 0.0,
+            stats: None,
         }
     }
 
     pub fn push(&mut self, frametime: Duration) {
         self.deque.push_back(frametime);
+
+        if self.history.len() == HISTORY_LENGTH {
+            self.history.pop_front();
+        }
+        self.history.push_back(frametime);
     }
 
     pub fn update_sample(&mut self) {
         let now = Instant::now();
         if now.duration_since(self.last_sample_instant).as_millis() >= self.sampling_interval_ms {
-            let frametime_sample_us = self
-                .deque
-                .iter()
-                .map(|duration: &Duration| duration.as_micros())
-                .sum::<u128>()
-                / self.deque.len() as u128;
-            self.last_sample_frametime_ms = frametime_sample_us as f64 / 1000f64;
+            // Keep the previous sample if no frame was pushed
+            if !self.deque.is_empty() {
+                let frametime_sample_us = self
+                    .deque
+                    .iter()
+                    .map(|duration: &Duration| duration.as_micros())
+                    .sum::<u128>()
+                    / self.deque.len() as u128;
+                self.last_sample_frametime_ms = frametime_sample_us as f64 / 1000f64;
+            }
+            self.stats = FrameTimeStats::new(self.history.iter().copied());
             self.deque.clear();
             self.last_sample_instant = now;
         }
     }
+
+    /// The most recent frame times, oldest first.
+    pub fn history(&self) -> impl ExactSizeIterator<Item = Duration> + '_ {
+        self.history.iter().copied()
+    }
+
+    /// Write the history as CSV with the frame number and its frame time in milliseconds.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "frame,frametime_ms")?;
+        for (frame, frametime) in self.history().enumerate() {
+            writeln!(writer, "{frame},{:.3}", frametime.as_secs_f64() * 1000.0)?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_stats() {
+        assert_eq!(FrameTimeStats::new([]), None);
+
+        // 1 to 1000 ms
+        let stats = FrameTimeStats::new((1..=1000).rev().map(Duration::from_millis)).unwrap();
+        assert_eq!(stats.min_ms, 1.0);
+        assert_eq!(stats.max_ms, 1000.0);
+        assert_eq!(stats.average_ms, 500.5);
+        assert_eq!(stats.median_ms, 500.0);
+        assert_eq!(stats.p95_ms, 950.0);
+        assert_eq!(stats.p99_ms, 990.0);
+        assert_eq!(stats.low_1_percent_ms, 995.5);
+        assert_eq!(stats.low_0_1_percent_ms, 1000.0);
+
+        let stats = FrameTimeStats::new([Duration::from_millis(4)]).unwrap();
+        assert_eq!(stats.low_0_1_percent_ms, 4.0);
+        assert_eq!(stats.p99_ms, 4.0);
+    }
+
+    #[test]
+    fn keeps_a_rolling_history() {
+        let mut metrics = FrameTimeMetrics::new(0);
+        // Sampling without frames keeps the previous sample instead of dividing by zero
+        metrics.update_sample();
+        assert_eq!(metrics.last_sample_frametime_ms, 0.0);
+        assert_eq!(metrics.stats, None);
+
+        for frame in 0..HISTORY_LENGTH + 2 {
+            metrics.push(Duration::from_micros(frame as u64));
+        }
+        metrics.update_sample();
+        assert_eq!(metrics.history().len(), HISTORY_LENGTH);
+        assert!((metrics.stats.unwrap().min_ms - 0.002).abs() < 1e-9);
+
+        let mut csv = Vec::new();
+        metrics.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("frame,frametime_ms"));
+        assert_eq!(lines.next(), Some("0,0.002"));
+        assert_eq!(lines.count(), HISTORY_LENGTH - 1);
+    }
 }
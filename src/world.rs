@@ -1,22 +1,58 @@
-use std::{collections::HashMap, time::Instant};
+use std::{array, collections::HashMap, time::Instant};
 
 use crate::renderer::CubeFaceInstance;
-use crate::world::chunk::Chunk;
+use crate::world::{
+    blocks::{Block, Direction},
+    chunk::Chunk,
+    visibility::VisibilityGraph,
+};
+use glam::Vec3;
 use noise::Simplex;
 
 pub mod blocks;
 pub mod camera;
 pub mod chunk;
+pub mod interaction;
+pub mod player;
+pub mod time;
+pub mod visibility;
 
 pub const CHUNK_WIDTH_BITS: u32 = 5;
 pub const CHUNK_DIMENSIONS: i32 = 2_i32.pow(CHUNK_WIDTH_BITS);
 pub const WORLD_HEIGHT: i32 = 256;
 pub const VERTICAL_CHUNK_COUNT: usize = (WORLD_HEIGHT / CHUNK_DIMENSIONS) as usize;
 
+/// Number of mesh detail levels per chunk. Level `n` renders cells of `2^n` blocks.
+pub const LOD_LEVELS: usize = 4;
+
+pub struct ChunkMesh {
+    /// Cube face instances, indexed by level of detail
+    pub instances: [Vec<CubeFaceInstance>; LOD_LEVELS],
+    pub visibility: VisibilityGraph,
+}
+
+/// Block hit by a ray cast into the world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RaycastHit {
+    /// World position of the hit block
+    pub position: (i32, i32, i32),
+    /// Face of the block the ray entered through
+    pub face: Direction,
+    pub block: Block,
+}
+
+/// Chunk column generated by `World::generate_column`, not yet part of the world.
+pub struct GeneratedColumn {
+    u: i32,
+    w: i32,
+    chunks: [Chunk; VERTICAL_CHUNK_COUNT],
+    meshes: [ChunkMesh; VERTICAL_CHUNK_COUNT],
+}
+
 pub struct World {
     noise: Simplex,
     pub chunk_columns: HashMap<(i32, i32), [Chunk; VERTICAL_CHUNK_COUNT]>,
-    pub meshed_chunks: HashMap<(i32, i32, i32), Vec<CubeFaceInstance>>,
+    pub meshed_chunks: HashMap<(i32, i32, i32), ChunkMesh>,
 }
 
 impl World {
@@ -28,20 +64,22 @@ impl World {
         }
     }
 
-    pub fn create_chunks(&mut self, u: i32, w: i32) {
-        let start_instant = Instant::now();
+    /// Noise used for terrain generation. It's cheap to copy, so chunks can be generated without
+    /// holding a lock on the world.
+    pub fn terrain_noise(&self) -> Simplex {
+        self.noise
+    }
 
-        if self.chunk_columns.contains_key(&(u, w)) {
-            panic!("Chunks at [u={}, w={}] already generated", u, w);
-        }
+    /// Generate and mesh the chunk column at `u`, `w`. Runs independently of the world, the
+    /// result is added with `insert_column`.
+    pub fn generate_column(noise: &Simplex, u: i32, w: i32) -> GeneratedColumn {
+        let start_instant = Instant::now();
 
-        let chunk_column = Chunk::generate_stack(&self.noise, u, w);
-        self.chunk_columns
-            .insert((u, w), Chunk::generate_stack(&self.noise, u, w));
-        for chunk in chunk_column {
-            self.meshed_chunks
-                .insert((chunk.u, chunk.v, chunk.w), chunk.generate_mesh());
-        }
+        let chunks = Chunk::generate_stack(noise, u, w);
+        let meshes = chunks.each_ref().map(|chunk| ChunkMesh {
+            instances: array::from_fn(|lod| chunk.generate_mesh(lod as u32)),
+            visibility: VisibilityGraph::compute(chunk),
+        });
 
         println!(
             "Generating chunks at [u={}, w={}] took {}ms",
@@ -49,5 +87,280 @@ impl World {
             w,
             start_instant.elapsed().as_millis()
         );
+
+        GeneratedColumn {
+            u,
+            w,
+            chunks,
+            meshes,
+        }
+    }
+
+    /// Add a generated chunk column. An already loaded column is kept, since it may contain edited
+    /// blocks.
+    pub fn insert_column(&mut self, column: GeneratedColumn) {
+        let GeneratedColumn {
+            u,
+            w,
+            chunks,
+            meshes,
+        } = column;
+        if self.chunk_columns.contains_key(&(u, w)) {
+            return;
+        }
+
+        for (chunk, mesh) in chunks.iter().zip(meshes) {
+            self.meshed_chunks.insert((chunk.u, chunk.v, chunk.w), mesh);
+        }
+        self.chunk_columns.insert((u, w), chunks);
+    }
+
+    /// Remove all chunk columns that are more than `distance` chunks away from the given column.
+    pub fn unload_chunks_outside(&mut self, u: i32, w: i32, distance: i32) {
+        let is_outside = |chunk_u: i32, chunk_w: i32| {
+            (chunk_u - u).abs() > distance || (chunk_w - w).abs() > distance
+        };
+
+        self.chunk_columns
+            .retain(|&(chunk_u, chunk_w), _| !is_outside(chunk_u, chunk_w));
+        self.meshed_chunks
+            .retain(|&(chunk_u, _, chunk_w), _| !is_outside(chunk_u, chunk_w));
+    }
+
+    /// Whether all chunk columns up to `distance` chunks away from the column containing
+    /// `position` are loaded.
+    pub fn is_loaded_around(&self, position: Vec3, distance: i32) -> bool {
+        let u = (position.x.floor() as i32).div_euclid(CHUNK_DIMENSIONS);
+        let w = (position.z.floor() as i32).div_euclid(CHUNK_DIMENSIONS);
+        (u - distance..=u + distance).all(|chunk_u| {
+            (w - distance..=w + distance)
+                .all(|chunk_w| self.chunk_columns.contains_key(&(chunk_u, chunk_w)))
+        })
+    }
+
+    /// Block at a world position, `None` if the position isn't loaded or outside of the world.
+    pub fn block_at(&self, x: i32, y: i32, z: i32) -> Option<Block> {
+        if !(0..WORLD_HEIGHT).contains(&y) {
+            return None;
+        }
+
+        let column = self.chunk_columns.get(&(
+            x.div_euclid(CHUNK_DIMENSIONS),
+            z.div_euclid(CHUNK_DIMENSIONS),
+        ))?;
+        let chunk = &column[(y / CHUNK_DIMENSIONS) as usize];
+        Some(*chunk.at(
+            x.rem_euclid(CHUNK_DIMENSIONS),
+            y % CHUNK_DIMENSIONS,
+            z.rem_euclid(CHUNK_DIMENSIONS),
+        ))
+    }
+
+    /// Replace the block at a world position and remesh all chunks containing it, including the
+    /// padding of neighbouring chunks. Returns `false` if the position isn't loaded or outside of
+    /// the world.
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: Block) -> bool {
+        if self.block_at(x, y, z).is_none() {
+            return false;
+        }
+
+        let (chunk_u, chunk_v, chunk_w) = (
+            x.div_euclid(CHUNK_DIMENSIONS),
+            y.div_euclid(CHUNK_DIMENSIONS),
+            z.div_euclid(CHUNK_DIMENSIONS),
+        );
+        let is_in_padded_chunk = |coordinate: i32, chunk: i32| {
+            (-1..=CHUNK_DIMENSIONS).contains(&(coordinate - chunk * CHUNK_DIMENSIONS))
+        };
+
+        let mut changed_chunks = Vec::new();
+        for u in chunk_u - 1..=chunk_u + 1 {
+            for v in
+                (chunk_v - 1..=chunk_v + 1).filter(|v| (0..VERTICAL_CHUNK_COUNT as i32).contains(v))
+            {
+                for w in chunk_w - 1..=chunk_w + 1 {
+                    if !(is_in_padded_chunk(x, u)
+                        && is_in_padded_chunk(y, v)
+                        && is_in_padded_chunk(z, w))
+                    {
+                        continue;
+                    }
+
+                    if let Some(column) = self.chunk_columns.get_mut(&(u, w)) {
+                        *column[v as usize].at_mut(
+                            x - u * CHUNK_DIMENSIONS,
+                            y - v * CHUNK_DIMENSIONS,
+                            z - w * CHUNK_DIMENSIONS,
+                        ) = block;
+                        changed_chunks.push((u, v, w));
+                    }
+                }
+            }
+        }
+
+        for (u, v, w) in changed_chunks {
+            let chunk = &self.chunk_columns[&(u, w)][v as usize];
+            self.meshed_chunks.insert(
+                (u, v, w),
+                ChunkMesh {
+                    instances: array::from_fn(|lod| chunk.generate_mesh(lod as u32)),
+                    visibility: VisibilityGraph::compute(chunk),
+                },
+            );
+        }
+
+        true
+    }
+
+    /// Find the first solid block along a ray with a DDA traversal of the voxel grid, see
+    /// Amanatides & Woo, "A Fast Voxel Traversal Algorithm for Ray Tracing". Blocks further than
+    /// `max_distance` away from `origin` aren't hit.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RaycastHit> {
+        let direction = direction.normalize_or_zero();
+        if direction == Vec3::ZERO {
+            return None;
+        }
+
+        let mut position = origin.floor().as_ivec3();
+        let step = direction.signum().as_ivec3();
+        // Ray length needed to cross one block along each axis
+        let t_delta = direction.recip().abs();
+        // Ray length until the next block boundary along each axis, never reached along axes the
+        // ray is parallel to
+        let boundary = Vec3::select(
+            direction.cmpgt(Vec3::ZERO),
+            position.as_vec3() + 1.0,
+            position.as_vec3(),
+        );
+        let mut t_max = Vec3::select(
+            direction.cmpeq(Vec3::ZERO),
+            Vec3::INFINITY,
+            (boundary - origin) / direction,
+        );
+
+        // A ray starting inside of a block hits the face pointing back along the ray
+        let dominant_axis = min_axis(-direction.abs());
+        let mut face = Direction::ALL[dominant_axis * 2 + (step[dominant_axis] < 0) as usize];
+
+        loop {
+            if let Some(block) = self
+                .block_at(position.x, position.y, position.z)
+                .filter(|block| block.is_opaque())
+            {
+                return Some(RaycastHit {
+                    position: position.into(),
+                    face,
+                    block,
+                });
+            }
+
+            let axis = min_axis(t_max);
+            if t_max[axis] > max_distance {
+                return None;
+            }
+
+            position[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+            // Moving in positive direction enters the block through its negative face
+            face = Direction::ALL[axis * 2 + (step[axis] < 0) as usize];
+        }
+    }
+}
+
+/// Index of the smallest component of `vector`.
+fn min_axis(vector: Vec3) -> usize {
+    if vector.x <= vector.y && vector.x <= vector.z {
+        0
+    } else if vector.y <= vector.z {
+        1
+    } else {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::vec3;
+    use noise::Constant;
+
+    /// World with flat terrain: stone up to y=7 and grass at y=8.
+    pub(super) fn flat_world() -> World {
+        let mut world = World::new(0);
+        for u in -1..=0 {
+            for w in -1..=0 {
+                world
+                    .chunk_columns
+                    .insert((u, w), Chunk::generate_stack(&Constant::new(0.0), u, w));
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn raycast_hits_top_face() {
+        let world = flat_world();
+        let hit = world.raycast(vec3(5.5, 20.5, 7.5), vec3(0.0, -1.0, 0.0), 16.0);
+        assert_eq!(
+            hit,
+            Some(RaycastHit {
+                position: (5, 8, 7),
+                face: Direction::Y,
+                block: Block::GRASS,
+            })
+        );
+
+        let hit = world.raycast(vec3(-3.5, 12.0, -0.5), vec3(0.1, -1.0, -0.2), 16.0);
+        assert_eq!(hit.map(|hit| hit.position), Some((-4, 8, -2)));
+    }
+
+    #[test]
+    fn raycast_starting_inside_block() {
+        let world = flat_world();
+        let hit = world.raycast(vec3(0.5, 5.5, 0.5), vec3(1.0, 0.0, 0.0), 16.0);
+        assert_eq!(
+            hit,
+            Some(RaycastHit {
+                position: (0, 5, 0),
+                face: Direction::NegX,
+                block: Block::STONE,
+            })
+        );
+    }
+
+    #[test]
+    fn checks_loaded_columns() {
+        let world = flat_world();
+        assert!(world.is_loaded_around(vec3(-0.5, 100.0, 31.5), 0));
+        assert!(!world.is_loaded_around(vec3(-0.5, 100.0, 32.5), 0));
+        // Only a 2x2 area of columns is loaded
+        assert!(!world.is_loaded_around(vec3(0.5, 100.0, 0.5), 1));
+    }
+
+    #[test]
+    fn set_block_updates_neighbour_padding() {
+        let mut world = flat_world();
+        assert!(world.set_block(-1, 8, 0, Block::AIR));
+        assert_eq!(world.block_at(-1, 8, 0), Some(Block::AIR));
+        // The block is also part of the padding of the chunk column at u=0
+        assert_eq!(*world.chunk_columns[&(0, 0)][0].at(-1, 8, 0), Block::AIR);
+        assert_eq!(*world.chunk_columns[&(0, -1)][0].at(-1, 8, 32), Block::AIR);
+
+        assert!(!world.set_block(100, 8, 0, Block::STONE));
+        assert!(!world.set_block(0, WORLD_HEIGHT, 0, Block::STONE));
+    }
+
+    #[test]
+    fn raycast_misses() {
+        let world = flat_world();
+        assert_eq!(
+            world.raycast(vec3(5.5, 20.5, 7.5), vec3(0.0, -1.0, 0.0), 5.0),
+            None
+        );
+        assert_eq!(
+            world.raycast(vec3(5.5, 20.5, 7.5), vec3(0.0, 1.0, 0.0), 16.0),
+            None
+        );
+        assert_eq!(world.raycast(vec3(5.5, 20.5, 7.5), Vec3::ZERO, 16.0), None);
     }
 }
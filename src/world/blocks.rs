@@ -1,5 +1,6 @@
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Block {
     AIR,
     STONE,
@@ -12,6 +13,32 @@ pub enum Block {
 }
 
 impl Block {
+    pub const ALL: [Block; 8] = [
+        Block::AIR,
+        Block::STONE,
+        Block::GRASS,
+        Block::DIRT,
+        Block::SAND,
+        Block::GRAVEL,
+        Block::ANDESITE,
+        Block::SNOW,
+    ];
+
+    /// Name used for the block in resource packs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Block::AIR => "air",
+            Block::STONE => "stone",
+            Block::GRASS => "grass",
+            Block::DIRT => "dirt",
+            Block::SAND => "sand",
+            Block::GRAVEL => "gravel",
+            Block::ANDESITE => "andesite",
+            Block::SNOW => "snow",
+        }
+    }
+
+    /// Layer of the block's texture in the block texture array.
     pub fn texture_index(&self) -> u8 {
         match self {
             Block::AIR => panic!("{:?} doesn't feature a texture", self),
@@ -24,6 +51,11 @@ impl Block {
             Block::SNOW => 6,
         }
     }
+
+    /// Whether the block fully hides everything behind it.
+    pub fn is_opaque(&self) -> bool {
+        !matches!(self, Block::AIR)
+    }
 }
 
 #[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
@@ -36,3 +68,37 @@ pub enum Direction {
     NegZ = 4,
     Z = 5,
 }
+
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::NegX,
+        Direction::X,
+        Direction::NegY,
+        Direction::Y,
+        Direction::NegZ,
+        Direction::Z,
+    ];
+
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::NegX => Direction::X,
+            Direction::X => Direction::NegX,
+            Direction::NegY => Direction::Y,
+            Direction::Y => Direction::NegY,
+            Direction::NegZ => Direction::Z,
+            Direction::Z => Direction::NegZ,
+        }
+    }
+
+    /// Unit offset pointing in this direction.
+    pub fn offset(&self) -> (i32, i32, i32) {
+        match self {
+            Direction::NegX => (-1, 0, 0),
+            Direction::X => (1, 0, 0),
+            Direction::NegY => (0, -1, 0),
+            Direction::Y => (0, 1, 0),
+            Direction::NegZ => (0, 0, -1),
+            Direction::Z => (0, 0, 1),
+        }
+    }
+}
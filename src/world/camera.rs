@@ -1,7 +1,6 @@
-use std::{collections::HashSet, f32::consts::PI};
+use std::f32::consts::PI;
 
 use glam::{vec3, Mat4, Vec3};
-use winit::keyboard::KeyCode;
 
 struct Perspective {
     fov_y: f32,
@@ -31,10 +30,6 @@ impl View {
 pub struct CameraController {
     view: View,
     perspective: Perspective,
-    /// camera translation per second
-    speed: f32,
-    /// camera rotation per second and mouse movement step, multiplied by pi
-    sensitivity: f32,
     // horizontal camera orientation; 0.0 is facing towards X+
     yaw: f32,
     //
@@ -50,8 +45,6 @@ impl CameraController {
         aspect_ratio: f32,
         z_near: f32,
         z_far: f32,
-        speed: f32,
-        sensitivity: f32,
     ) -> Self {
         CameraController {
             view: View {
@@ -65,37 +58,26 @@ impl CameraController {
                 z_near,
                 z_far,
             },
-            speed,
-            sensitivity,
             yaw,
             pitch,
         }
     }
 
-    pub fn handle_input(
-        &mut self,
-        pressed_keys: &HashSet<KeyCode>,
-        mouse_movement: (f64, f64),
-        delta_s: f32,
-    ) {
-        let (dx, dy) = mouse_movement;
-        let time_adjusted_sensitivity = self.sensitivity * delta_s;
-        let time_adjusted_speed = self.speed * delta_s;
-        let mut speed_multiplier = 1.0;
-
-        let mut new_yaw = self.yaw - (dx as f32) * time_adjusted_sensitivity;
-        // Normalize yaw value
-        new_yaw %= 2.0;
-        if new_yaw < 0.0 {
-            new_yaw += 2.0;
-        }
+    /// Rotate the camera by `yaw` and `pitch` in half turns.
+    pub fn rotate(&mut self, (yaw, pitch): (f32, f32)) {
+        self.set_rotation(self.yaw + yaw, self.pitch + pitch);
+    }
 
-        let new_pitch = (self.pitch - (dy as f32) * time_adjusted_sensitivity).clamp(-0.5, 0.5);
+    /// Point the camera in the direction given by `yaw` and `pitch` in half turns. Positive pitch
+    /// looks up.
+    pub fn set_rotation(&mut self, yaw: f32, pitch: f32) {
+        // Normalize yaw value
+        let new_yaw = yaw.rem_euclid(2.0);
+        let new_pitch = pitch.clamp(-0.5, 0.5);
 
         let (yaw_sin, yaw_cos) = ((new_yaw) * PI).sin_cos();
         let (pitch_sin, pitch_cos) = ((new_pitch) * PI).sin_cos();
 
-        let xz_forward = vec3(yaw_cos, 0.0, yaw_sin);
         let xz_right = vec3(-yaw_sin, 0.0, yaw_cos);
 
         self.view.direction = vec3(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin);
@@ -103,34 +85,12 @@ impl CameraController {
         self.pitch = new_pitch;
 
         self.view.up = xz_right.cross(self.view.direction);
+    }
 
-        if pressed_keys.contains(&KeyCode::ShiftLeft) {
-            speed_multiplier = 3.0;
-        }
-
-        if pressed_keys.contains(&KeyCode::KeyW) {
-            self.view.eye += xz_forward * time_adjusted_speed * speed_multiplier;
-        }
-
-        if pressed_keys.contains(&KeyCode::KeyS) {
-            self.view.eye -= xz_forward * time_adjusted_speed * speed_multiplier;
-        }
-
-        if pressed_keys.contains(&KeyCode::KeyA) {
-            self.view.eye += xz_right * time_adjusted_speed * speed_multiplier;
-        }
-
-        if pressed_keys.contains(&KeyCode::KeyD) {
-            self.view.eye -= xz_right * time_adjusted_speed * speed_multiplier;
-        }
-
-        if pressed_keys.contains(&KeyCode::Space) {
-            self.view.eye.y += time_adjusted_speed * speed_multiplier;
-        }
-
-        if pressed_keys.contains(&KeyCode::ControlLeft) {
-            self.view.eye.y -= time_adjusted_speed * speed_multiplier;
-        }
+    /// Viewing direction projected onto the horizontal plane.
+    pub fn get_horizontal_direction(&self) -> Vec3 {
+        let (yaw_sin, yaw_cos) = (self.yaw * PI).sin_cos();
+        vec3(yaw_cos, 0.0, yaw_sin)
     }
 
     pub fn get_view_projection_matrix(&self) -> Mat4 {
@@ -141,11 +101,23 @@ impl CameraController {
         self.perspective.aspect_ratio = aspect_ratio;
     }
 
+    pub fn set_z_far(&mut self, z_far: f32) {
+        self.perspective.z_far = z_far;
+    }
+
     pub fn get_position(&self) -> Vec3 {
         self.view.eye
     }
 
-    #[allow(dead_code)]
+    pub fn set_position(&mut self, eye: Vec3) {
+        self.view.eye = eye;
+    }
+
+    /// Yaw and pitch in half turns.
+    pub fn get_rotation(&self) -> (f32, f32) {
+        (self.yaw, self.pitch)
+    }
+
     pub fn get_direction(&self) -> Vec3 {
         self.view.direction
     }
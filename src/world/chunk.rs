@@ -6,7 +6,7 @@ use crate::{
     world::CubeFaceInstance,
     world::{
         blocks::{Block, Direction},
-        CHUNK_DIMENSIONS, CHUNK_WIDTH_BITS, VERTICAL_CHUNK_COUNT, WORLD_HEIGHT,
+        CHUNK_DIMENSIONS, VERTICAL_CHUNK_COUNT, WORLD_HEIGHT,
     },
 };
 
@@ -101,57 +101,251 @@ impl Chunk {
         }
     }
 
-    pub fn generate_mesh(&self) -> Vec<CubeFaceInstance> {
+    /// Generate the cube face instances of the chunk at the given level of detail. At LOD `n`,
+    /// the chunk is downsampled into cells of `2^n` blocks in each dimension. A cell is solid if
+    /// any block inside it is solid, so coarser meshes never leave holes next to finer ones.
+    pub fn generate_mesh(&self, lod: u32) -> Vec<CubeFaceInstance> {
+        let cell_size = 1 << lod;
+        let cell_count = CHUNK_DIMENSIONS >> lod;
+        let cell_index = |x: i32, y: i32, z: i32| ((x * cell_count + y) * cell_count + z) as usize;
+
+        let mut cells = Vec::with_capacity(cell_count.pow(3) as usize);
+        for x in 0..cell_count {
+            for y in 0..cell_count {
+                for z in 0..cell_count {
+                    cells.push(self.downsample_cell(x, y, z, cell_size));
+                }
+            }
+        }
+
         let mut instances = Vec::new();
 
-        for x in 0..CHUNK_DIMENSIONS {
-            for z in 0..CHUNK_DIMENSIONS {
-                for y in 0..CHUNK_DIMENSIONS {
-                    if let Block::AIR = self.at(x, y, z) {
+        for x in 0..cell_count {
+            for z in 0..cell_count {
+                for y in 0..cell_count {
+                    let Some(block) = cells[cell_index(x, y, z)] else {
                         continue;
-                    }
+                    };
 
-                    let mut directions = Vec::with_capacity(6);
+                    for direction in Direction::ALL {
+                        let (dx, dy, dz) = direction.offset();
+                        let (nx, ny, nz) = (x + dx, y + dy, z + dz);
 
-                    if let Block::AIR = self.at(x - 1, y, z) {
-                        directions.push(Direction::NegX)
-                    }
-                    if let Block::AIR = self.at(x + 1, y, z) {
-                        directions.push(Direction::X)
-                    }
-                    if let Block::AIR = self.at(x, y - 1, z) {
-                        directions.push(Direction::NegY)
+                        let exposed = if (0..cell_count).contains(&nx)
+                            && (0..cell_count).contains(&ny)
+                            && (0..cell_count).contains(&nz)
+                        {
+                            cells[cell_index(nx, ny, nz)].is_none()
+                        } else {
+                            self.is_cell_face_exposed(x, y, z, cell_size, direction)
+                        };
+
+                        if !exposed {
+                            continue;
+                        }
+
+                        instances.push(CubeFaceInstance::new(
+                            [self.u, self.v, self.w],
+                            [x as u32, y as u32, z as u32],
+                            block.texture_index(),
+                            direction,
+                            lod,
+                        ));
                     }
-                    if let Block::AIR = self.at(x, y + 1, z) {
-                        directions.push(Direction::Y)
+                }
+            }
+        }
+
+        instances
+    }
+
+    /// Returns the topmost opaque block of the cell, or `None` if the cell only contains air.
+    fn downsample_cell(&self, x: i32, y: i32, z: i32, cell_size: i32) -> Option<Block> {
+        for by in (y * cell_size..(y + 1) * cell_size).rev() {
+            for bx in x * cell_size..(x + 1) * cell_size {
+                for bz in z * cell_size..(z + 1) * cell_size {
+                    let block = self.at(bx, by, bz);
+                    if block.is_opaque() {
+                        return Some(*block);
                     }
-                    if let Block::AIR = self.at(x, y, z - 1) {
-                        directions.push(Direction::NegZ)
+                }
+            }
+        }
+        None
+    }
+
+    /// Check whether a cell face on the chunk border touches any non-opaque block of the
+    /// neighboring chunk. Such faces act as skirts that hide cracks between different LODs.
+    fn is_cell_face_exposed(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+        cell_size: i32,
+        direction: Direction,
+    ) -> bool {
+        let (dx, dy, dz) = direction.offset();
+        for bx in x * cell_size..(x + 1) * cell_size {
+            for by in y * cell_size..(y + 1) * cell_size {
+                for bz in z * cell_size..(z + 1) * cell_size {
+                    let (nx, ny, nz) = (bx + dx, by + dy, bz + dz);
+                    if (0..CHUNK_DIMENSIONS).contains(&nx)
+                        && (0..CHUNK_DIMENSIONS).contains(&ny)
+                        && (0..CHUNK_DIMENSIONS).contains(&nz)
+                    {
+                        continue;
                     }
-                    if let Block::AIR = self.at(x, y, z + 1) {
-                        directions.push(Direction::Z)
+                    if !self.at(nx, ny, nz).is_opaque() {
+                        return true;
                     }
+                }
+            }
+        }
+        false
+    }
+}
 
-                    let tex_index = self.at(x, y, z).texture_index();
+#[cfg(test)]
+pub(super) mod tests {
+    use noise::Constant;
 
-                    let common_packed_bits: u32 = x as u32
-                        | ((y as u32) << CHUNK_WIDTH_BITS)
-                        | ((z as u32) << (CHUNK_WIDTH_BITS * 2))
-                        | ((tex_index as u32) << (CHUNK_WIDTH_BITS * 3));
+    use super::*;
+    use crate::world::{World, CHUNK_WIDTH_BITS};
 
-                    for direction in directions {
-                        let packed_bits =
-                            common_packed_bits | ((direction as u32) << (CHUNK_WIDTH_BITS * 3 + 8));
+    /// Chunk filled with `block`, including its padding.
+    pub(in crate::world) fn filled_chunk(block: Block) -> Chunk {
+        // Terrain at the bottom of the lowest chunk, so the second one is empty
+        let [_, mut chunk, ..] = Chunk::generate_stack(&Constant::new(-1.0), 0, 0);
+        for x in -1..=CHUNK_DIMENSIONS {
+            for y in -1..=CHUNK_DIMENSIONS {
+                for z in -1..=CHUNK_DIMENSIONS {
+                    *chunk.at_mut(x, y, z) = block;
+                }
+            }
+        }
+        chunk
+    }
 
-                        instances.push(CubeFaceInstance {
-                            chunk: [self.u, self.v, self.w],
-                            attributes: packed_bits,
-                        })
+    fn attributes(instances: &[CubeFaceInstance]) -> Vec<u32> {
+        instances
+            .iter()
+            .map(|instance| instance.attributes)
+            .collect()
+    }
+
+    /// Full detail mesh as generated before levels of detail existed: a face for each side of a
+    /// block next to air.
+    fn full_detail_mesh(chunk: &Chunk) -> Vec<u32> {
+        let mut attributes = Vec::new();
+        for x in 0..CHUNK_DIMENSIONS {
+            for z in 0..CHUNK_DIMENSIONS {
+                for y in 0..CHUNK_DIMENSIONS {
+                    let block = chunk.at(x, y, z);
+                    if *block == Block::AIR {
+                        continue;
+                    }
+                    for direction in Direction::ALL {
+                        let (dx, dy, dz) = direction.offset();
+                        if *chunk.at(x + dx, y + dy, z + dz) != Block::AIR {
+                            continue;
+                        }
+                        attributes.push(
+                            x as u32
+                                | ((y as u32) << CHUNK_WIDTH_BITS)
+                                | ((z as u32) << (CHUNK_WIDTH_BITS * 2))
+                                | ((block.texture_index() as u32) << (CHUNK_WIDTH_BITS * 3))
+                                | ((direction as u32) << (CHUNK_WIDTH_BITS * 3 + 8)),
+                        );
                     }
                 }
             }
         }
+        attributes
+    }
 
-        instances
+    #[test]
+    fn full_detail_mesh_is_unchanged() {
+        let noise = World::new(1234).terrain_noise();
+        let mut face_count = 0;
+        for chunk in Chunk::generate_stack(&noise, 2, -3) {
+            let instances = chunk.generate_mesh(0);
+            assert!(instances
+                .iter()
+                .all(|instance| instance.chunk == [2, chunk.v, -3]));
+            assert_eq!(attributes(&instances), full_detail_mesh(&chunk));
+            face_count += instances.len();
+        }
+        assert!(face_count > 0);
+    }
+
+    #[test]
+    fn coarse_cells_keep_solid_and_top_blocks() {
+        let mut chunk = filled_chunk(Block::AIR);
+        assert_eq!(chunk.downsample_cell(0, 0, 0, 2), None);
+
+        // A single solid block makes the whole cell solid
+        *chunk.at_mut(1, 0, 0) = Block::STONE;
+        assert_eq!(chunk.downsample_cell(0, 0, 0, 2), Some(Block::STONE));
+
+        // The surface block on top shows, not the majority below it
+        for x in 0..4 {
+            for z in 0..4 {
+                *chunk.at_mut(x, 0, z) = Block::STONE;
+                *chunk.at_mut(x, 1, z) = Block::STONE;
+                *chunk.at_mut(x, 2, z) = Block::DIRT;
+            }
+        }
+        *chunk.at_mut(3, 3, 3) = Block::GRASS;
+        assert_eq!(chunk.downsample_cell(0, 0, 0, 4), Some(Block::GRASS));
+        assert_eq!(chunk.downsample_cell(0, 0, 0, 2), Some(Block::STONE));
+        assert_eq!(chunk.downsample_cell(0, 1, 0, 2), Some(Block::DIRT));
+    }
+
+    #[test]
+    fn coarse_faces_at_cell_boundaries_against_air() {
+        let face = |x, y, z, direction| {
+            CubeFaceInstance::new(
+                [0, 1, 0],
+                [x, y, z],
+                Block::STONE.texture_index(),
+                direction,
+                1,
+            )
+            .attributes
+        };
+        let mut chunk = filled_chunk(Block::AIR);
+        // Two cells of the LOD 1 mesh, with an air block between them at full detail
+        *chunk.at_mut(0, 0, 0) = Block::STONE;
+        *chunk.at_mut(2, 0, 0) = Block::STONE;
+        assert_eq!(chunk.generate_mesh(0).len(), 12);
+
+        let mut mesh = attributes(&chunk.generate_mesh(1));
+        mesh.sort();
+        let mut expected = vec![
+            face(0, 0, 0, Direction::NegX),
+            face(0, 0, 0, Direction::NegY),
+            face(0, 0, 0, Direction::Y),
+            face(0, 0, 0, Direction::NegZ),
+            face(0, 0, 0, Direction::Z),
+            face(1, 0, 0, Direction::X),
+            face(1, 0, 0, Direction::NegY),
+            face(1, 0, 0, Direction::Y),
+            face(1, 0, 0, Direction::NegZ),
+            face(1, 0, 0, Direction::Z),
+        ];
+        expected.sort();
+        assert_eq!(mesh, expected);
+
+        // Border faces are hidden once all neighbouring blocks are solid
+        for y in 0..2 {
+            for z in 0..2 {
+                *chunk.at_mut(-1, y, z) = Block::STONE;
+            }
+        }
+        let mesh = attributes(&chunk.generate_mesh(1));
+        assert!(!mesh.contains(&face(0, 0, 0, Direction::NegX)));
+        *chunk.at_mut(-1, 1, 1) = Block::AIR;
+        let mesh = attributes(&chunk.generate_mesh(1));
+        assert!(mesh.contains(&face(0, 0, 0, Direction::NegX)));
     }
 }
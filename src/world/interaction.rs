@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use crate::{
+    resources::BlockDefinition,
+    world::{blocks::Block, RaycastHit, World},
+};
+
+/// Breaking and placing of blocks by the player.
+pub struct BlockInteraction {
+    /// Block placed by the place block action
+    pub selected_block: Block,
+    /// Seconds needed to break each block, indexed by `Block as usize`
+    hardness: [f32; Block::ALL.len()],
+    /// Block being broken and seconds spent breaking it so far
+    breaking: Option<(RaycastHit, f32)>,
+    /// Edits to apply with the next `apply_edits`
+    pending_edits: Vec<((i32, i32, i32), Block)>,
+}
+
+impl BlockInteraction {
+    pub fn new(block_definitions: &HashMap<String, BlockDefinition>) -> Self {
+        BlockInteraction {
+            selected_block: Block::STONE,
+            hardness: Block::ALL.map(|block| {
+                block_definitions
+                    .get(block.name())
+                    .map_or(1.0, |definition| definition.hardness)
+            }),
+            breaking: None,
+            pending_edits: Vec::new(),
+        }
+    }
+
+    /// Keep breaking the targeted block while `is_breaking` is set. Looking at another block
+    /// starts over.
+    pub fn update(&mut self, target: Option<RaycastHit>, is_breaking: bool, delta_s: f32) {
+        self.breaking = match (target, is_breaking) {
+            (Some(target), true) => {
+                let elapsed = match self.breaking {
+                    Some((breaking, elapsed)) if breaking.position == target.position => {
+                        elapsed + delta_s
+                    }
+                    _ => delta_s,
+                };
+                Some((target, elapsed))
+            }
+            _ => None,
+        };
+
+        if self.break_progress() >= 1.0 {
+            if let Some((target, _)) = self.breaking.take() {
+                self.pending_edits.push((target.position, Block::AIR));
+            }
+        }
+    }
+
+    /// Place the selected block against the hit face of the target. Nothing is placed where
+    /// `is_occupied` is true, e.g. inside of the player.
+    pub fn place(
+        &mut self,
+        target: Option<RaycastHit>,
+        is_occupied: impl Fn((i32, i32, i32)) -> bool,
+    ) {
+        let Some(target) = target else {
+            return;
+        };
+
+        let (x, y, z) = target.position;
+        let (dx, dy, dz) = target.face.offset();
+        let position = (x + dx, y + dy, z + dz);
+        if !is_occupied(position) {
+            self.pending_edits.push((position, self.selected_block));
+        }
+    }
+
+    /// Fraction of the targeted block that is broken, from 0 to 1.
+    pub fn break_progress(&self) -> f32 {
+        self.breaking.map_or(0.0, |(target, elapsed)| {
+            let hardness = self.hardness[target.block as usize];
+            if hardness > 0.0 {
+                (elapsed / hardness).min(1.0)
+            } else {
+                1.0
+            }
+        })
+    }
+
+    /// Apply queued edits to the world. Returns whether any block changed.
+    pub fn apply_edits(&mut self, world: &mut World) -> bool {
+        let mut changed = false;
+        for ((x, y, z), block) in self.pending_edits.drain(..) {
+            // Blocks can only be placed into air and only solid blocks can be broken
+            let can_edit = match world.block_at(x, y, z) {
+                Some(Block::AIR) => block != Block::AIR,
+                Some(_) => block == Block::AIR,
+                None => false,
+            };
+            if can_edit {
+                changed |= world.set_block(x, y, z, block);
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{blocks::Direction, player::Player, tests::flat_world};
+    use glam::vec3;
+
+    fn block_interaction() -> BlockInteraction {
+        let definition = |hardness| BlockDefinition {
+            texture: String::new(),
+            hardness,
+        };
+        BlockInteraction::new(&HashMap::from([
+            ("stone".to_owned(), definition(1.5)),
+            ("grass".to_owned(), definition(0.5)),
+        ]))
+    }
+
+    /// Hit of the grass block at `x`, 8, 7 from above.
+    fn grass_hit(x: i32) -> Option<RaycastHit> {
+        Some(RaycastHit {
+            position: (x, 8, 7),
+            face: Direction::Y,
+            block: Block::GRASS,
+        })
+    }
+
+    #[test]
+    fn breaking_takes_the_block_hardness() {
+        let mut world = flat_world();
+        let mut interaction = block_interaction();
+
+        interaction.update(grass_hit(5), true, 0.25);
+        assert!((interaction.break_progress() - 0.5).abs() < 1e-6);
+        assert!(!interaction.apply_edits(&mut world));
+
+        interaction.update(grass_hit(5), true, 0.25);
+        assert_eq!(interaction.break_progress(), 0.0);
+        assert!(interaction.apply_edits(&mut world));
+        assert_eq!(world.block_at(5, 8, 7), Some(Block::AIR));
+
+        let stone_hit = world.raycast(vec3(5.5, 20.5, 7.5), vec3(0.0, -1.0, 0.0), 16.0);
+        assert_eq!(stone_hit.map(|hit| hit.block), Some(Block::STONE));
+        interaction.update(stone_hit, true, 1.0);
+        assert!((interaction.break_progress() - 1.0 / 1.5).abs() < 1e-6);
+        assert!(!interaction.apply_edits(&mut world));
+    }
+
+    #[test]
+    fn breaking_starts_over_on_another_target() {
+        let mut interaction = block_interaction();
+
+        interaction.update(grass_hit(5), true, 0.25);
+        interaction.update(grass_hit(6), true, 0.25);
+        assert!((interaction.break_progress() - 0.5).abs() < 1e-6);
+
+        // Releasing the button also starts over
+        interaction.update(grass_hit(6), false, 0.25);
+        assert_eq!(interaction.break_progress(), 0.0);
+        interaction.update(grass_hit(6), true, 0.25);
+        assert!((interaction.break_progress() - 0.5).abs() < 1e-6);
+
+        interaction.update(None, true, 0.25);
+        assert_eq!(interaction.break_progress(), 0.0);
+    }
+
+    #[test]
+    fn placement_rules() {
+        let mut world = flat_world();
+        let mut interaction = block_interaction();
+        let player = Player::new(vec3(5.5, 9.0, 7.5));
+        let is_occupied = |position| player.intersects_block(position);
+
+        // Into the player standing on the target
+        interaction.place(grass_hit(5), is_occupied);
+        assert!(!interaction.apply_edits(&mut world));
+        assert_eq!(world.block_at(5, 9, 7), Some(Block::AIR));
+
+        // Next to the player
+        interaction.place(grass_hit(3), is_occupied);
+        assert!(interaction.apply_edits(&mut world));
+        assert_eq!(world.block_at(3, 9, 7), Some(Block::STONE));
+
+        // Into a solid block, below the target
+        let below = grass_hit(3).map(|hit| RaycastHit {
+            face: Direction::NegY,
+            ..hit
+        });
+        interaction.selected_block = Block::SAND;
+        interaction.place(below, is_occupied);
+        assert!(!interaction.apply_edits(&mut world));
+        assert_eq!(world.block_at(3, 7, 7), Some(Block::STONE));
+
+        // Air can't be broken
+        let air_hit = Some(RaycastHit {
+            position: (2, 9, 7),
+            face: Direction::Y,
+            block: Block::AIR,
+        });
+        interaction.update(air_hit, true, 10.0);
+        assert!(!interaction.apply_edits(&mut world));
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::HashSet;
+
+use glam::{vec3, Vec3};
+
+use crate::{
+    input::Action,
+    world::{World, WORLD_HEIGHT},
+};
+
+/// Side length of the player's bounding box on the X and Z axes
+const PLAYER_WIDTH: f32 = 0.6;
+const PLAYER_HEIGHT: f32 = 1.8;
+/// Height of the camera above the player's feet
+const EYE_HEIGHT: f32 = 1.62;
+/// Obstacles up to this height are climbed without jumping
+const STEP_HEIGHT: f32 = 1.0;
+
+const WALK_SPEED: f32 = 4.3;
+const SPRINT_MULTIPLIER: f32 = 1.5;
+const FLY_SPEED: f32 = 10.0;
+const FLY_SPRINT_MULTIPLIER: f32 = 3.0;
+/// Downwards acceleration in blocks per second squared
+const GRAVITY: f32 = 32.0;
+const TERMINAL_VELOCITY: f32 = 78.0;
+/// Initial upwards velocity of a jump, reaching a height of about 1.25 blocks
+const JUMP_VELOCITY: f32 = 9.0;
+/// Tolerance for boxes touching each other after their movement was clipped
+const EPSILON: f32 = 1e-4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovementMode {
+    /// Affected by gravity and collides with solid blocks
+    Walking,
+    /// Flies freely through terrain
+    Flying,
+}
+
+impl MovementMode {
+    pub fn next(&self) -> MovementMode {
+        match self {
+            MovementMode::Walking => MovementMode::Flying,
+            MovementMode::Flying => MovementMode::Walking,
+        }
+    }
+}
+
+/// Axis aligned bounding box
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Aabb {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Aabb {
+    fn offset(&self, offset: Vec3) -> Aabb {
+        Aabb {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    /// Box covering all positions of this box moved by up to `movement`.
+    fn expand_towards(&self, movement: Vec3) -> Aabb {
+        Aabb {
+            min: self.min + movement.min(Vec3::ZERO),
+            max: self.max + movement.max(Vec3::ZERO),
+        }
+    }
+
+    /// Shorten `movement` along `axis`, so this box stops when touching `obstacle`. Obstacles
+    /// already overlapping the box don't block it, so it can't get stuck inside of them.
+    fn clip_movement(&self, obstacle: &Aabb, axis: usize, movement: f32) -> f32 {
+        let overlaps_on_other_axes = (0..3).filter(|other| *other != axis).all(|other| {
+            self.max[other] > obstacle.min[other] + EPSILON
+                && self.min[other] < obstacle.max[other] - EPSILON
+        });
+        if !overlaps_on_other_axes {
+            return movement;
+        }
+
+        if movement > 0.0 && self.max[axis] <= obstacle.min[axis] + EPSILON {
+            movement.min((obstacle.min[axis] - self.max[axis]).max(0.0))
+        } else if movement < 0.0 && self.min[axis] >= obstacle.max[axis] - EPSILON {
+            movement.max((obstacle.max[axis] - self.min[axis]).min(0.0))
+        } else {
+            movement
+        }
+    }
+}
+
+/// The player's body, which the camera is attached to.
+pub struct Player {
+    /// Center of the bottom of the bounding box
+    position: Vec3,
+    /// Position before the last update, for interpolating between updates
+    previous_position: Vec3,
+    velocity: Vec3,
+    on_ground: bool,
+    /// Whether the player was put on top of the terrain after it loaded
+    spawned: bool,
+    pub movement_mode: MovementMode,
+}
+
+impl Player {
+    pub fn new(position: Vec3) -> Self {
+        Player {
+            position,
+            previous_position: position,
+            velocity: Vec3::ZERO,
+            on_ground: false,
+            spawned: false,
+            movement_mode: MovementMode::Walking,
+        }
+    }
+
+    /// Eye position between the last two updates, `alpha` is 0 at the previous and 1 at the
+    /// latest update.
+    pub fn get_eye_position(&self, alpha: f32) -> Vec3 {
+        self.previous_position.lerp(self.position, alpha) + vec3(0.0, EYE_HEIGHT, 0.0)
+    }
+
+    /// Move the player according to the active actions. `forward` is the horizontal viewing
+    /// direction.
+    pub fn update(
+        &mut self,
+        world: &World,
+        actions: &HashSet<Action>,
+        forward: Vec3,
+        delta_s: f32,
+    ) {
+        if !self.spawned {
+            self.spawn(world);
+        }
+        self.previous_position = self.position;
+
+        let right = vec3(-forward.z, 0.0, forward.x);
+        let axis_input = |positive: Action, negative: Action| {
+            actions.contains(&positive) as i32 as f32 - actions.contains(&negative) as i32 as f32
+        };
+        let horizontal_input = (forward * axis_input(Action::MoveForward, Action::MoveBackward)
+            + right * axis_input(Action::MoveLeft, Action::MoveRight))
+        .normalize_or_zero();
+        let sprinting = actions.contains(&Action::Sprint);
+
+        match self.movement_mode {
+            MovementMode::Flying => {
+                let speed = FLY_SPEED
+                    * if sprinting {
+                        FLY_SPRINT_MULTIPLIER
+                    } else {
+                        1.0
+                    };
+                let vertical_input = axis_input(Action::Jump, Action::Descend);
+                self.velocity = (horizontal_input + vec3(0.0, vertical_input, 0.0)) * speed;
+                self.position += self.velocity * delta_s;
+                self.on_ground = false;
+            }
+            MovementMode::Walking => {
+                let speed = WALK_SPEED * if sprinting { SPRINT_MULTIPLIER } else { 1.0 };
+                self.velocity.x = horizontal_input.x * speed;
+                self.velocity.z = horizontal_input.z * speed;
+                self.velocity.y = (self.velocity.y - GRAVITY * delta_s).max(-TERMINAL_VELOCITY);
+                if self.on_ground && actions.contains(&Action::Jump) {
+                    self.velocity.y = JUMP_VELOCITY;
+                }
+
+                self.move_and_collide(world, self.velocity * delta_s);
+            }
+        }
+    }
+
+    /// Put the player on top of the terrain once the chunk column below them is loaded.
+    fn spawn(&mut self, world: &World) {
+        let (x, z) = (
+            self.position.x.floor() as i32,
+            self.position.z.floor() as i32,
+        );
+        if let Some(y) = (0..WORLD_HEIGHT).rev().find(|&y| {
+            world
+                .block_at(x, y, z)
+                .is_some_and(|block| block.is_opaque())
+        }) {
+            self.position.y = (y + 1) as f32;
+            self.spawned = true;
+        }
+    }
+
+    /// Whether the block at `position` overlaps the player's bounding box.
+    pub fn intersects_block(&self, (x, y, z): (i32, i32, i32)) -> bool {
+        let bounding_box = self.bounding_box();
+        let block_min = vec3(x as f32, y as f32, z as f32);
+        bounding_box.min.cmplt(block_min + Vec3::ONE).all()
+            && bounding_box.max.cmpgt(block_min).all()
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let half_width = PLAYER_WIDTH / 2.0;
+        Aabb {
+            min: self.position - vec3(half_width, 0.0, half_width),
+            max: self.position + vec3(half_width, PLAYER_HEIGHT, half_width),
+        }
+    }
+
+    /// Move by `movement`, stopping at solid blocks and stepping up low obstacles.
+    fn move_and_collide(&mut self, world: &World, movement: Vec3) {
+        let bounding_box = self.bounding_box();
+        let mut moved = Player::sweep(world, bounding_box, movement);
+
+        let blocked_horizontally = moved.x != movement.x || moved.z != movement.z;
+        if self.on_ground && blocked_horizontally {
+            // Try the same movement from STEP_HEIGHT higher up, then move back down
+            let up = Player::sweep(world, bounding_box, vec3(0.0, STEP_HEIGHT, 0.0));
+            let raised = bounding_box.offset(up);
+            let horizontal = Player::sweep(world, raised, vec3(movement.x, 0.0, movement.z));
+            let down = Player::sweep(
+                world,
+                raised.offset(horizontal),
+                vec3(0.0, -up.y + movement.y.min(0.0), 0.0),
+            );
+            let stepped = up + horizontal + down;
+
+            if stepped.x * stepped.x + stepped.z * stepped.z
+                > moved.x * moved.x + moved.z * moved.z + EPSILON
+            {
+                moved = stepped;
+            }
+        }
+
+        // Landing or hitting the ceiling stops vertical movement
+        let blocked_vertically = (moved.y - movement.y).abs() > EPSILON;
+        self.on_ground = blocked_vertically && movement.y < 0.0;
+        if blocked_vertically {
+            self.velocity.y = 0.0;
+        }
+
+        self.position += moved;
+    }
+
+    /// Movement of `bounding_box` by `movement`, clipped against solid blocks. Axes are resolved
+    /// one after another, starting with the vertical one.
+    fn sweep(world: &World, bounding_box: Aabb, movement: Vec3) -> Vec3 {
+        let obstacles = Player::solid_blocks(world, bounding_box.expand_towards(movement));
+
+        let mut bounding_box = bounding_box;
+        let mut moved = Vec3::ZERO;
+        for axis in [1, 0, 2] {
+            let distance = obstacles.iter().fold(movement[axis], |distance, obstacle| {
+                bounding_box.clip_movement(obstacle, axis, distance)
+            });
+
+            let mut offset = Vec3::ZERO;
+            offset[axis] = distance;
+            bounding_box = bounding_box.offset(offset);
+            moved[axis] = distance;
+        }
+        moved
+    }
+
+    /// Bounding boxes of all blocks inside `region` the player can't move through. Unloaded
+    /// chunks and the bottom of the world are solid, so the player can't fall out of the world.
+    fn solid_blocks(world: &World, region: Aabb) -> Vec<Aabb> {
+        let min = region.min.floor().as_ivec3();
+        let max = region.max.ceil().as_ivec3();
+
+        let mut blocks = Vec::new();
+        for x in min.x..max.x {
+            for y in min.y..max.y {
+                for z in min.z..max.z {
+                    let is_solid = match world.block_at(x, y, z) {
+                        Some(block) => block.is_opaque(),
+                        None => y < WORLD_HEIGHT,
+                    };
+                    if is_solid {
+                        let min = vec3(x as f32, y as f32, z as f32);
+                        blocks.push(Aabb {
+                            min,
+                            max: min + Vec3::ONE,
+                        });
+                    }
+                }
+            }
+        }
+        blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{blocks::Block, tests::flat_world};
+
+    /// Simulate a second of walking in `forward` direction
+    fn walk(player: &mut Player, world: &World, actions: &[Action], forward: Vec3) {
+        let actions = actions.iter().copied().collect();
+        for _ in 0..60 {
+            player.update(world, &actions, forward, 1.0 / 60.0);
+        }
+    }
+
+    #[test]
+    fn spawns_on_terrain_and_stays_on_ground() {
+        let world = flat_world();
+        let mut player = Player::new(vec3(5.5, 0.0, 5.5));
+        walk(&mut player, &world, &[], Vec3::X);
+        assert_eq!(player.position, vec3(5.5, 9.0, 5.5));
+        assert!(player.on_ground);
+    }
+
+    #[test]
+    fn falls_and_lands() {
+        let world = flat_world();
+        let mut player = Player::new(vec3(5.5, 0.0, 5.5));
+        player.spawned = true;
+        player.position.y = 20.0;
+        walk(&mut player, &world, &[], Vec3::X);
+        assert!((player.position.y - 9.0).abs() < EPSILON);
+        assert!(player.on_ground);
+    }
+
+    #[test]
+    fn walls_block_movement() {
+        let mut world = flat_world();
+        for y in 9..11 {
+            world.set_block(8, y, 5, Block::STONE);
+        }
+
+        let mut player = Player::new(vec3(5.5, 0.0, 5.5));
+        walk(&mut player, &world, &[Action::MoveForward], Vec3::X);
+        assert!((player.position.x - (8.0 - PLAYER_WIDTH / 2.0)).abs() < EPSILON);
+        assert_eq!(player.position.y, 9.0);
+    }
+
+    #[test]
+    fn steps_up_single_blocks() {
+        let mut world = flat_world();
+        for x in 8..12 {
+            world.set_block(x, 9, 5, Block::STONE);
+        }
+
+        let mut player = Player::new(vec3(5.5, 0.0, 5.5));
+        walk(&mut player, &world, &[Action::MoveForward], Vec3::X);
+        assert!(player.position.x > 8.0);
+        assert!((player.position.y - 10.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn jumps() {
+        let world = flat_world();
+        let mut player = Player::new(vec3(5.5, 0.0, 5.5));
+        let actions = [Action::Jump].into_iter().collect();
+        let mut max_height: f32 = 0.0;
+        for _ in 0..30 {
+            player.update(&world, &actions, Vec3::X, 1.0 / 60.0);
+            max_height = max_height.max(player.position.y);
+        }
+        assert!(max_height > 10.0 && max_height < 11.0);
+    }
+
+    #[test]
+    fn flying_ignores_terrain() {
+        let world = flat_world();
+        let mut player = Player::new(vec3(5.5, 0.0, 5.5));
+        player.movement_mode = MovementMode::Flying;
+        walk(&mut player, &world, &[Action::Descend], Vec3::X);
+        assert!(player.position.y < 9.0);
+    }
+}
@@ -0,0 +1,86 @@
+use std::f32::consts::TAU;
+
+use glam::{vec3, Vec3};
+
+const DAY_SKY_COLOR: Vec3 = vec3(135.0 / 255.0, 206.0 / 255.0, 235.0 / 255.0);
+const NIGHT_SKY_COLOR: Vec3 = vec3(0.02, 0.03, 0.08);
+const SUNSET_SKY_COLOR: Vec3 = vec3(0.95, 0.55, 0.3);
+
+/// Time of day of the world, advancing with the simulation.
+pub struct WorldTime {
+    /// Fraction of the current day, 0.0 is midnight, 0.25 sunrise, 0.5 noon and 0.75 sunset
+    time_of_day: f32,
+    /// Real time duration of a full day in seconds
+    day_length_s: f32,
+    pub paused: bool,
+}
+
+impl WorldTime {
+    pub fn new(time_of_day: f32, day_length_s: f32) -> Self {
+        WorldTime {
+            time_of_day: time_of_day.rem_euclid(1.0),
+            day_length_s,
+            paused: false,
+        }
+    }
+
+    pub fn advance(&mut self, delta_s: f32) {
+        if !self.paused {
+            self.set_time_of_day(self.time_of_day + delta_s / self.day_length_s);
+        }
+    }
+
+    pub fn get_time_of_day(&self) -> f32 {
+        self.time_of_day
+    }
+
+    pub fn set_time_of_day(&mut self, time_of_day: f32) {
+        self.time_of_day = time_of_day.rem_euclid(1.0);
+    }
+
+    /// Normalized direction pointing towards the sun. The sun rises at X+ and sets at X-.
+    pub fn sun_direction(&self) -> Vec3 {
+        let (sin, cos) = ((self.time_of_day - 0.25) * TAU).sin_cos();
+        // Tilt the sun path slightly so faces pointing in Z directions aren't lit equally
+        vec3(cos, sin, 0.25).normalize()
+    }
+
+    /// Intensity of the directional sunlight, 0.0 while the sun is below the horizon.
+    pub fn sun_intensity(&self) -> f32 {
+        0.8 * smoothstep(-0.1, 0.3, self.sun_direction().y)
+    }
+
+    /// Light intensity reaching every face regardless of its orientation.
+    pub fn ambient_light(&self) -> f32 {
+        0.2 + 0.2 * self.daylight()
+    }
+
+    /// Sky color, also used for the fog.
+    pub fn sky_color(&self) -> Vec3 {
+        let sun_height = self.sun_direction().y;
+        let color = NIGHT_SKY_COLOR.lerp(DAY_SKY_COLOR, self.daylight());
+        // Tint the sky around sunrise and sunset
+        let sunset_factor = (1.0 - sun_height.abs() / 0.25).clamp(0.0, 1.0);
+        color.lerp(SUNSET_SKY_COLOR, 0.5 * sunset_factor)
+    }
+
+    /// Sky color straight above the viewer, darker than the sky color at the horizon.
+    pub fn zenith_color(&self) -> Vec3 {
+        0.6 * NIGHT_SKY_COLOR.lerp(DAY_SKY_COLOR, self.daylight())
+    }
+
+    /// Stars fade in after sunset and fade out before sunrise.
+    pub fn star_visibility(&self) -> f32 {
+        1.0 - smoothstep(-0.3, 0.0, self.sun_direction().y)
+    }
+
+    /// 0.0 during the night, 1.0 during the day, blending around sunrise and sunset.
+    fn daylight(&self) -> f32 {
+        smoothstep(-0.2, 0.2, self.sun_direction().y)
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -0,0 +1,232 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::world::{
+    blocks::Direction, chunk::Chunk, World, CHUNK_DIMENSIONS, VERTICAL_CHUNK_COUNT,
+};
+
+/// Records which pairs of chunk faces are connected through non-opaque blocks, so a chunk entered
+/// through one face can be seen through the other.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VisibilityGraph {
+    /// Symmetric 6x6 adjacency matrix, bit `a * 6 + b` is set if face `a` connects to face `b`
+    connections: u64,
+}
+
+impl VisibilityGraph {
+    pub fn connects(&self, a: Direction, b: Direction) -> bool {
+        self.connections & (1 << (a as u64 * 6 + b as u64)) != 0
+    }
+
+    fn connect(&mut self, a: Direction, b: Direction) {
+        self.connections |= 1 << (a as u64 * 6 + b as u64);
+        self.connections |= 1 << (b as u64 * 6 + a as u64);
+    }
+
+    /// Flood fill over the non-opaque blocks of the chunk and connect all faces touched by the
+    /// same region.
+    pub fn compute(chunk: &Chunk) -> Self {
+        let mut graph = VisibilityGraph::default();
+        let index =
+            |x: i32, y: i32, z: i32| ((x * CHUNK_DIMENSIONS + y) * CHUNK_DIMENSIONS + z) as usize;
+
+        let mut visited = vec![false; CHUNK_DIMENSIONS.pow(3) as usize];
+        let mut queue = VecDeque::new();
+
+        for x in 0..CHUNK_DIMENSIONS {
+            for y in 0..CHUNK_DIMENSIONS {
+                for z in 0..CHUNK_DIMENSIONS {
+                    if visited[index(x, y, z)] || chunk.at(x, y, z).is_opaque() {
+                        continue;
+                    }
+
+                    let mut touched_faces: Vec<Direction> = Vec::with_capacity(6);
+                    visited[index(x, y, z)] = true;
+                    queue.push_back((x, y, z));
+
+                    while let Some((x, y, z)) = queue.pop_front() {
+                        for direction in Direction::ALL {
+                            let (dx, dy, dz) = direction.offset();
+                            let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+
+                            if !(0..CHUNK_DIMENSIONS).contains(&nx)
+                                || !(0..CHUNK_DIMENSIONS).contains(&ny)
+                                || !(0..CHUNK_DIMENSIONS).contains(&nz)
+                            {
+                                if !touched_faces.contains(&direction) {
+                                    touched_faces.push(direction);
+                                }
+                                continue;
+                            }
+
+                            if visited[index(nx, ny, nz)] || chunk.at(nx, ny, nz).is_opaque() {
+                                continue;
+                            }
+                            visited[index(nx, ny, nz)] = true;
+                            queue.push_back((nx, ny, nz));
+                        }
+                    }
+
+                    for a in &touched_faces {
+                        for b in &touched_faces {
+                            graph.connect(*a, *b);
+                        }
+                    }
+                }
+            }
+        }
+
+        graph
+    }
+}
+
+/// Breadth-first search through the visibility graphs of all meshed chunks, starting at the chunk
+/// containing the camera. A chunk is only entered if it is reachable through connected faces and
+/// the search never steps back in a direction opposite to one it already went.
+///
+/// Returns the coordinates of all chunks that can potentially be seen from the camera chunk.
+pub fn visible_chunks(
+    world: &World,
+    camera_chunk: (i32, i32, i32),
+    render_distance: i32,
+) -> Vec<(i32, i32, i32)> {
+    let (camera_u, camera_v, camera_w) = camera_chunk;
+    // Outside the vertical world bounds, start from the closest existing chunk instead
+    let start = (
+        camera_u,
+        camera_v.clamp(0, VERTICAL_CHUNK_COUNT as i32 - 1),
+        camera_w,
+    );
+
+    let in_range = |(u, v, w): (i32, i32, i32)| {
+        (u - camera_u).abs() <= render_distance
+            && (w - camera_w).abs() <= render_distance
+            && (0..VERTICAL_CHUNK_COUNT as i32).contains(&v)
+    };
+
+    let mut visible = vec![start];
+    let mut visited = HashSet::from([start]);
+    // (chunk, face through which the chunk was entered, bitmask of directions travelled so far)
+    let mut queue = VecDeque::from([(start, None, 0_u8)]);
+
+    while let Some((chunk, entry_face, travelled)) = queue.pop_front() {
+        let Some(mesh) = world.meshed_chunks.get(&chunk) else {
+            continue;
+        };
+
+        for direction in Direction::ALL {
+            if travelled & (1 << direction.opposite() as u8) != 0 {
+                continue;
+            }
+            if entry_face.is_some_and(|entry_face| !mesh.visibility.connects(entry_face, direction))
+            {
+                continue;
+            }
+
+            let (du, dv, dw) = direction.offset();
+            let neighbor = (chunk.0 + du, chunk.1 + dv, chunk.2 + dw);
+            if !in_range(neighbor) || !visited.insert(neighbor) {
+                continue;
+            }
+
+            visible.push(neighbor);
+            queue.push_back((
+                neighbor,
+                Some(direction.opposite()),
+                travelled | (1 << direction as u8),
+            ));
+        }
+    }
+
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{blocks::Block, chunk::tests::filled_chunk, ChunkMesh};
+
+    /// Solid chunk with a hollow inside that doesn't touch any face.
+    fn cave_chunk() -> Chunk {
+        let mut chunk = filled_chunk(Block::STONE);
+        for x in 8..24 {
+            for y in 8..24 {
+                for z in 8..24 {
+                    *chunk.at_mut(x, y, z) = Block::AIR;
+                }
+            }
+        }
+        chunk
+    }
+
+    /// World with only the given chunks meshed.
+    fn world_with(chunks: &[((i32, i32, i32), Chunk)]) -> World {
+        let mut world = World::new(0);
+        for (position, chunk) in chunks {
+            world.meshed_chunks.insert(
+                *position,
+                ChunkMesh {
+                    instances: Default::default(),
+                    visibility: VisibilityGraph::compute(chunk),
+                },
+            );
+        }
+        world
+    }
+
+    #[test]
+    fn solid_chunk_connects_no_faces() {
+        for chunk in [filled_chunk(Block::STONE), cave_chunk()] {
+            let graph = VisibilityGraph::compute(&chunk);
+            for a in Direction::ALL {
+                for b in Direction::ALL {
+                    assert!(!graph.connects(a, b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn air_chunk_connects_all_faces() {
+        let graph = VisibilityGraph::compute(&filled_chunk(Block::AIR));
+        for a in Direction::ALL {
+            for b in Direction::ALL {
+                assert!(graph.connects(a, b));
+            }
+        }
+    }
+
+    #[test]
+    fn sealed_cave_is_culled_from_outside() {
+        let world = world_with(&[
+            ((0, 0, 0), filled_chunk(Block::AIR)),
+            ((1, 0, 0), cave_chunk()),
+            ((2, 0, 0), filled_chunk(Block::AIR)),
+        ]);
+
+        let visible = visible_chunks(&world, (0, 0, 0), 2);
+        // The outside of the cave is visible, but not what's behind it
+        assert!(visible.contains(&(1, 0, 0)));
+        assert!(!visible.contains(&(2, 0, 0)));
+    }
+
+    #[test]
+    fn skips_chunks_behind_solid_terrain() {
+        let world = world_with(&[
+            ((0, 0, 0), filled_chunk(Block::AIR)),
+            ((1, 0, 0), filled_chunk(Block::STONE)),
+            ((2, 0, 0), filled_chunk(Block::AIR)),
+            ((0, 0, 1), filled_chunk(Block::AIR)),
+            ((1, 0, 1), filled_chunk(Block::AIR)),
+            ((2, 0, 1), filled_chunk(Block::AIR)),
+        ]);
+
+        let visible = visible_chunks(&world, (0, 0, 0), 2);
+        for open in [(1, 0, 0), (0, 0, 1), (1, 0, 1), (2, 0, 1)] {
+            assert!(visible.contains(&open), "{open:?} should be visible");
+        }
+        // Only reachable by turning back towards the camera
+        assert!(!visible.contains(&(2, 0, 0)));
+        // Out of range
+        assert!(!visible.contains(&(3, 0, 1)));
+    }
+}